@@ -37,7 +37,7 @@ mod in_game {
         mut paperdolls: ResMut<Assets<PaperdollAsset>>,
         resources: Res<Resources>,
     ) {
-        commands.spawn(Camera2d::default());
+        commands.spawn(Camera2d);
 
         let paperdoll_asset = paperdolls.get_mut(&resources.asset).unwrap();
 
@@ -39,6 +39,8 @@ mod in_game {
     #[derive(Component)]
     struct TextForSlotFragment(u32);
 
+    type ButtonInteraction<'a> = (&'a Interaction, &'a ButtonAction);
+
     pub struct InGamePlugin;
 
     impl Plugin for InGamePlugin {
@@ -54,10 +56,7 @@ mod in_game {
 
     // Handles user interactions.
     fn button_action(
-        interaction_query: Query<
-            (&Interaction, &ButtonAction),
-            (Changed<Interaction>, With<Button>),
-        >,
+        interaction_query: Query<ButtonInteraction, (Changed<Interaction>, With<Button>)>,
         mut ev_paperdoll: MessageWriter<PaperdollChangedEvent>,
         mut paperdolls: ResMut<Assets<PaperdollAsset>>,
         resources: Res<Resources>,
@@ -130,7 +129,7 @@ mod in_game {
             ..default()
         };
 
-        commands.spawn(Camera2d::default());
+        commands.spawn(Camera2d);
 
         let Some(paperdoll_asset) = paperdolls.get_mut(&resources.asset) else {
             commands.spawn((Text::new("Failed to load assets"), text_font.clone()));
@@ -1,17 +1,46 @@
-use std::sync::atomic::{AtomicU32, Ordering};
+use std::{
+    collections::BTreeMap,
+    io::Write,
+    path::Path,
+    sync::atomic::{AtomicU32, Ordering},
+};
 
 use anyhow::{anyhow, bail, Result};
 use bevy::{
     asset::RenderAssetUsages,
+    log::warn,
     platform::collections::HashMap,
     prelude::*,
     reflect::TypePath,
     render::render_resource::{Extent3d, TextureDimension, TextureFormat},
 };
-use paperdoll_tar::paperdoll::{Doll, Fragment, Paperdoll, PaperdollFactory, Slot};
+use paperdoll_tar::paperdoll::{
+    Doll, Fragment, ImageData, Manifest, Paperdoll, PaperdollFactory, Point, Slot,
+};
+
+use crate::{
+    blend::SlotBlend,
+    builder::{PaperdollBuilder, SlotFill},
+    clip::ClipMask,
+    constraint::{Constraint, ConstraintPolicy, Resolution, Resolver},
+    defaults::PaperdollDefaults,
+    export::Export,
+    item::Item,
+    lint::Diagnostic,
+    loader::{self, PaperdollLoaderSettings},
+    mask::MaskColors,
+    merge::{self, MergeReport},
+    outfit::OutfitProblem,
+    palette::PaletteSwap,
+    render::{self, Modifiers, Renderer},
+    variant::{FragmentVariant, VariantCondition},
+};
 
 pub type PaperdollId = u32;
 
+/// A slot map along with the slots and fragments dropped from it.
+type MappedSelections = (HashMap<u32, u32>, Vec<(u32, u32)>);
+
 enum SetSlotBy {
     Empty,
     FragmentId(u32),
@@ -20,23 +49,357 @@ enum SetSlotBy {
 /// A structure storing the paperdoll asset and all paperdolls you created.
 #[derive(Asset, TypePath)]
 pub struct PaperdollAsset {
+    constraint_policy: ConstraintPolicy,
+    constraints: Vec<Constraint>,
+    diagnostics: Vec<Diagnostic>,
     factory: PaperdollFactory,
+    fragment_clips: HashMap<u32, Vec<ClipMask>>,
+    fragment_hidden_slots: HashMap<u32, Vec<u32>>,
+    fragment_links: HashMap<(u32, u32), (u32, u32)>,
+    fragment_masks: HashMap<u32, ImageData>,
+    fragment_variants: HashMap<u32, Vec<FragmentVariant>>,
+    id_to_modifiers: HashMap<PaperdollId, Modifiers>,
     id_to_paperdoll: HashMap<PaperdollId, Paperdoll>,
     id_to_texture: HashMap<PaperdollId, Image>,
+    items: BTreeMap<String, Item>,
+    slot_blends: HashMap<u32, SlotBlend>,
+    slot_defaults: HashMap<(u32, u32), u32>,
 }
 
 impl PaperdollAsset {
     pub fn new(factory: PaperdollFactory) -> Self {
         Self {
+            constraint_policy: ConstraintPolicy::default(),
+            constraints: vec![],
+            diagnostics: vec![],
             factory,
+            fragment_clips: HashMap::new(),
+            fragment_hidden_slots: HashMap::new(),
+            fragment_links: HashMap::new(),
+            fragment_masks: HashMap::new(),
+            fragment_variants: HashMap::new(),
+            id_to_modifiers: HashMap::new(),
             id_to_paperdoll: HashMap::new(),
             id_to_texture: HashMap::new(),
+            items: BTreeMap::new(),
+            slot_blends: HashMap::new(),
+            slot_defaults: HashMap::new(),
+        }
+    }
+
+    /// Adds a constraint restricting which fragments can be used together.
+    ///
+    /// Existing paperdolls are not changed. Constraints are checked when slots are changed, see
+    /// [`Self::set_constraint_policy`].
+    ///
+    /// # Errors
+    ///
+    /// - Will return an error if any of the slots or fragments does not exist.
+    pub fn add_constraint(&mut self, constraint: Constraint) -> Result<()> {
+        match &constraint {
+            Constraint::ExclusiveSlots(slots) => {
+                for slot_id in slots {
+                    self.get_slot(*slot_id)?;
+                }
+            }
+            Constraint::ConflictsWithSlots { fragment, slots } => {
+                self.get_fragment(*fragment)?;
+
+                for slot_id in slots {
+                    self.get_slot(*slot_id)?;
+                }
+            }
+            Constraint::ConflictsWithFragments {
+                fragment,
+                fragments,
+            }
+            | Constraint::Requires {
+                fragment,
+                fragments,
+            } => {
+                self.get_fragment(*fragment)?;
+
+                for fragment_id in fragments {
+                    self.get_fragment(*fragment_id)?;
+                }
+            }
+        }
+
+        self.constraints.push(constraint);
+
+        Ok(())
+    }
+
+    /// Adds a fragment drawn with the given image, eg. a decal made by the player.
+    ///
+    /// The pivot is in pixels of the image. The fragment becomes a candidate of the given slots.
+    ///
+    /// Returns the id of the new fragment.
+    ///
+    /// # Errors
+    ///
+    /// - Will return an error if the image is empty or its pixels can not be read.
+    /// - Will return an error if any of the slots does not exist.
+    pub fn add_fragment(
+        &mut self,
+        image: &Image,
+        pivot: Vec2,
+        desc: impl Into<String>,
+        slots: &[u32],
+    ) -> Result<u32> {
+        for slot_id in slots {
+            self.get_slot(*slot_id)?;
+        }
+
+        let image = render::image_data_from_image(image)?;
+
+        let fragment_id = self.factory.add_fragment()?;
+
+        if let Some(fragment) = self.factory.get_fragment_mut(fragment_id) {
+            fragment.desc = desc.into();
+            fragment.image = image;
+            fragment.pivot = Point {
+                x: pivot.x,
+                y: pivot.y,
+            };
+        }
+
+        for slot_id in slots {
+            if let Some(slot) = self.factory.get_slot_mut(*slot_id) {
+                if !slot.candidates.contains(&fragment_id) {
+                    slot.candidates.push(fragment_id);
+                }
+            }
+        }
+
+        Ok(fragment_id)
+    }
+
+    /// Adds a clip mask to the given fragment, hiding pixels of other slots while it is used.
+    ///
+    /// # Errors
+    ///
+    /// - Will return an error if the fragment does not exist.
+    pub fn add_fragment_clip_mask(&mut self, fragment_id: u32, clip_mask: ClipMask) -> Result<()> {
+        self.get_fragment(fragment_id)?;
+
+        self.fragment_clips
+            .entry(fragment_id)
+            .or_default()
+            .push(clip_mask);
+
+        self.update_textures_using_fragment(fragment_id)
+    }
+
+    /// Adds a variant to the given fragment, drawn instead of it while the condition is met.
+    ///
+    /// Variants are checked in the order they are added, the first one whose condition is met is
+    /// drawn.
+    ///
+    /// # Errors
+    ///
+    /// - Will return an error if any of the fragments does not exist.
+    pub fn add_fragment_variant(
+        &mut self,
+        fragment_id: u32,
+        variant: FragmentVariant,
+    ) -> Result<()> {
+        self.get_fragment(fragment_id)?;
+        self.get_fragment(variant.fragment)?;
+
+        self.fragment_variants
+            .entry(fragment_id)
+            .or_default()
+            .push(variant);
+
+        self.update_textures_using_fragment(fragment_id)
+    }
+
+    /// Adds an item, replacing the item with the same name.
+    ///
+    /// # Errors
+    ///
+    /// - Will return an error if any of the dolls or fragments does not exist.
+    pub fn add_item(&mut self, item: Item) -> Result<()> {
+        for (doll_id, fragment_id) in &item.fragments {
+            self.factory
+                .get_doll(*doll_id)
+                .ok_or(anyhow!("Doll with id '{}' not found.", doll_id))?;
+
+            self.get_fragment(*fragment_id)?;
+        }
+
+        self.items.insert(item.name.clone(), item);
+
+        Ok(())
+    }
+
+    /// Changes the doll of the paperdoll, keeping the fragments of the slots shared by both dolls.
+    ///
+    /// Fragments of items are replaced by the fragments of the same items for the new doll, see
+    /// [`Self::add_item`]. Required slots left empty use their default fragment.
+    ///
+    /// Returns the slots and fragments which were dropped.
+    ///
+    /// # Errors
+    ///
+    /// - Will return an error if the paperdoll or the doll does not exist.
+    pub fn change_doll(&mut self, id: PaperdollId, doll_id: u32) -> Result<Vec<(u32, u32)>> {
+        let paperdoll = self
+            .id_to_paperdoll
+            .get(&id)
+            .ok_or(anyhow!("Paperdoll with id '{}' not found.", id))?;
+
+        let (slot_map, dropped) = self.map_selections(paperdoll, doll_id)?;
+
+        let mut paperdoll = self.factory.builder().doll(doll_id).build();
+
+        paperdoll.slot_map.extend(slot_map);
+
+        self.id_to_paperdoll.insert(id, paperdoll);
+
+        self.update_texture(id)?;
+
+        Ok(dropped)
+    }
+
+    /// Removes all constraints.
+    pub fn clear_constraints(&mut self) {
+        self.constraints.clear();
+    }
+
+    /// Removes all clip masks of the given fragment.
+    pub fn clear_fragment_clip_masks(&mut self, fragment_id: u32) -> Result<()> {
+        if self.fragment_clips.remove(&fragment_id).is_some() {
+            self.update_textures_using_fragment(fragment_id)?;
+        }
+
+        Ok(())
+    }
+
+    /// Removes all variants of the given fragment.
+    pub fn clear_fragment_variants(&mut self, fragment_id: u32) -> Result<()> {
+        if self.fragment_variants.remove(&fragment_id).is_some() {
+            self.update_textures_using_fragment(fragment_id)?;
+        }
+
+        Ok(())
+    }
+
+    /// Removes the palette swap applied to the whole paperdoll.
+    pub fn clear_palette_swap(&mut self, id: PaperdollId) -> Result<()> {
+        self.modifiers_mut(id)?.palette_swap = None;
+
+        self.update_texture(id)
+    }
+
+    /// Removes the blend override of the given slot, using the blend set for all paperdolls
+    /// instead, see [`Self::set_slot_blend`].
+    pub fn clear_paperdoll_slot_blend(&mut self, id: PaperdollId, slot_id: u32) -> Result<()> {
+        self.modifiers_mut(id)?.slot_blends.remove(&slot_id);
+
+        self.update_texture(id)
+    }
+
+    /// Removes the mask colors of the given slot.
+    pub fn clear_slot_mask_colors(&mut self, id: PaperdollId, slot_id: u32) -> Result<()> {
+        self.modifiers_mut(id)?.slot_mask_colors.remove(&slot_id);
+
+        self.update_texture(id)
+    }
+
+    /// Removes the palette swap applied to the given slot.
+    pub fn clear_slot_palette_swap(&mut self, id: PaperdollId, slot_id: u32) -> Result<()> {
+        self.modifiers_mut(id)?.slot_palette_swaps.remove(&slot_id);
+
+        self.update_texture(id)
+    }
+
+    /// Creates a paperdoll with the same doll, fragments and modifiers as the given one.
+    ///
+    /// Returns the id of the new paperdoll.
+    ///
+    /// # Errors
+    ///
+    /// - Will return an error if the paperdoll does not exist.
+    pub fn clone_paperdoll(&mut self, id: PaperdollId) -> Result<PaperdollId> {
+        let paperdoll = self
+            .id_to_paperdoll
+            .get(&id)
+            .ok_or(anyhow!("Paperdoll with id '{}' not found.", id))?;
+
+        let mut clone = self.factory.builder().doll(paperdoll.doll).build();
+
+        clone.slot_map.extend(
+            paperdoll
+                .slot_map
+                .iter()
+                .map(|(slot_id, fragment_id)| (*slot_id, *fragment_id)),
+        );
+
+        let clone_id = get_id();
+
+        self.id_to_paperdoll.insert(clone_id, clone);
+
+        if let Some(modifiers) = self.id_to_modifiers.get(&id).cloned() {
+            self.id_to_modifiers.insert(clone_id, modifiers);
+        }
+
+        self.update_texture(clone_id)?;
+
+        Ok(clone_id)
+    }
+
+    /// Gets all constraints of this asset.
+    pub fn constraints(&self) -> &[Constraint] {
+        &self.constraints
+    }
+
+    /// Copies the fragments and modifiers of one paperdoll to another one, keeping the doll of the
+    /// target paperdoll.
+    ///
+    /// If the dolls differ, the fragments are mapped as in [`Self::change_doll`].
+    ///
+    /// Returns the slots and fragments which could not be copied.
+    ///
+    /// # Errors
+    ///
+    /// - Will return an error if any of the paperdolls does not exist.
+    pub fn copy_outfit(&mut self, from: PaperdollId, to: PaperdollId) -> Result<Vec<(u32, u32)>> {
+        let doll_id = self
+            .id_to_paperdoll
+            .get(&to)
+            .map(|paperdoll| paperdoll.doll)
+            .ok_or(anyhow!("Paperdoll with id '{}' not found.", to))?;
+
+        let paperdoll = self
+            .id_to_paperdoll
+            .get(&from)
+            .ok_or(anyhow!("Paperdoll with id '{}' not found.", from))?;
+
+        let (slot_map, dropped) = self.map_selections(paperdoll, doll_id)?;
+
+        if let Some(paperdoll) = self.id_to_paperdoll.get_mut(&to) {
+            paperdoll.slot_map.clear();
+            paperdoll.slot_map.extend(slot_map);
         }
+
+        match self.id_to_modifiers.get(&from).cloned() {
+            Some(modifiers) => self.id_to_modifiers.insert(to, modifiers),
+            None => self.id_to_modifiers.remove(&to),
+        };
+
+        self.update_texture(to)?;
+
+        Ok(dropped)
     }
 
     /// Creates a paperdoll from this asset.
     ///
     /// Returns the id used to refer to this paperdoll for later usage.
+    ///
+    /// Slots use their default fragments, see [`Self::set_default_fragment`]. See
+    /// [`Self::paperdoll`] for creating a paperdoll with validated, explicit selections.
     pub fn create_paperdoll(&mut self, doll_id: u32) -> PaperdollId {
         let mut paperdoll = self.factory.builder().doll(doll_id).build();
 
@@ -48,29 +411,89 @@ impl PaperdollAsset {
             .map(|doll| {
                 doll.slots
                     .iter()
-                    .map(|slot_id| self.factory.get_slot(*slot_id))
-                    .map_while(|slot| slot)
+                    .filter_map(|slot_id| self.factory.get_slot(*slot_id))
                     .collect::<Vec<&Slot>>()
             })
             .unwrap_or_default();
 
         for slot in slots {
-            if !slot.required {
-                continue;
-            }
-
-            if let Some(fragment_id) = slot.candidates.first() {
-                paperdoll.slot_map.insert(slot.id(), *fragment_id);
+            if let Some(fragment_id) = self.default_fragment(paperdoll.doll, slot) {
+                paperdoll.slot_map.insert(slot.id(), fragment_id);
             }
         }
 
         self.id_to_paperdoll.insert(id, paperdoll);
 
-        self.update_texture(id);
+        // The paperdoll is still usable, its texture is rendered again on the next change.
+        if let Err(err) = self.update_texture(id) {
+            warn!("Failed to render paperdoll {}: {}", id, err);
+        }
 
         id
     }
 
+    /// Gets the problems found when loading this asset, see [`crate::PaperdollLoaderSettings`].
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// Gets the slots which would be emptied to satisfy the constraints if the given slot was set
+    /// to the given fragment, or to empty if the fragment is [`None`].
+    ///
+    /// Slots are reported as if the policy was [`ConstraintPolicy::Resolve`].
+    ///
+    /// # Errors
+    ///
+    /// - Will return an error if the change is invalid or the constraints can not be satisfied.
+    pub fn displaced_slots(
+        &self,
+        id: PaperdollId,
+        slot_id: u32,
+        fragment_id: Option<u32>,
+    ) -> Result<Vec<u32>> {
+        let set_slot_by = match fragment_id {
+            Some(fragment_id) => SetSlotBy::FragmentId(fragment_id),
+            None => SetSlotBy::Empty,
+        };
+
+        let fragment_id = self.check_slot_change(slot_id, &set_slot_by)?;
+
+        let resolution = self.resolve(id, &[(slot_id, fragment_id)], ConstraintPolicy::Resolve)?;
+
+        Ok(resolution.displaced)
+    }
+
+    /// Gets the id of the doll the paperdoll is based on.
+    pub fn doll_of(&self, id: PaperdollId) -> Option<u32> {
+        self.id_to_paperdoll
+            .get(&id)
+            .map(|paperdoll| paperdoll.doll)
+    }
+
+    /// Creates the asset from the bytes of a paperdoll file (.ppd), eg. embedded with
+    /// `include_bytes!`.
+    ///
+    /// The asset is loaded with the default [`PaperdollLoaderSettings`].
+    ///
+    /// # Errors
+    ///
+    /// - Will return an error if the bytes are not a valid paperdoll file, see
+    ///   [`crate::PaperdollLoaderError`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        Ok(loader::read_ppd(
+            "<bytes>",
+            bytes,
+            &PaperdollLoaderSettings::default(),
+        )?)
+    }
+
+    /// Gets the fragment used by default in the given slot for the given doll.
+    pub fn get_default_fragment(&self, doll_id: u32, slot_id: u32) -> Option<u32> {
+        self.factory
+            .get_slot(slot_id)
+            .and_then(|slot| self.default_fragment(doll_id, slot))
+    }
+
     /// Gets all dolls available in this asset.
     pub fn get_dolls(&self) -> Vec<&Doll> {
         self.factory
@@ -92,17 +515,57 @@ impl PaperdollAsset {
             .unwrap_or_default()
     }
 
+    /// Gets the item with the given name.
+    pub fn get_item(&self, name: &str) -> Option<&Item> {
+        self.items.get(name)
+    }
+
+    /// Gets the names of the items used in this paperdoll, sorted.
+    pub fn get_items(&self, id: PaperdollId) -> Vec<&str> {
+        let Some(paperdoll) = self.id_to_paperdoll.get(&id) else {
+            return vec![];
+        };
+
+        self.items
+            .values()
+            .filter(|item| {
+                item.fragments
+                    .get(&paperdoll.doll)
+                    .is_some_and(|fragment_id| {
+                        paperdoll
+                            .slot_map
+                            .values()
+                            .any(|used_id| used_id == fragment_id)
+                    })
+            })
+            .map(|item| item.name.as_str())
+            .collect()
+    }
+
+    /// Gets the paperdoll with the given id.
+    pub fn get_paperdoll(&self, id: PaperdollId) -> Option<&Paperdoll> {
+        self.id_to_paperdoll.get(&id)
+    }
+
+    /// Gets the fragment drawn in this slot, which differs from the fragment used in this slot if
+    /// one of its variants applies, see [`Self::add_fragment_variant`].
+    pub fn get_slot_drawn_fragment(&self, id: PaperdollId, slot_id: u32) -> Option<&Fragment> {
+        let fragment_id = self
+            .id_to_paperdoll
+            .get(&id)
+            .and_then(|paperdoll| self.renderer().drawn_fragment(paperdoll, slot_id));
+
+        fragment_id.and_then(|fragment_id| self.factory.get_fragment(fragment_id))
+    }
+
     /// Gets the fragment currently used in this slot.
     pub fn get_slot_fragment(&self, id: PaperdollId, slot_id: u32) -> Option<&Fragment> {
         let fragment_id = self
             .id_to_paperdoll
             .get(&id)
-            .map(|paperdoll| paperdoll.slot_map.get(&slot_id))
-            .flatten();
+            .and_then(|paperdoll| paperdoll.slot_map.get(&slot_id));
 
-        fragment_id
-            .map(|fragment_id| self.factory.get_fragment(*fragment_id))
-            .flatten()
+        fragment_id.and_then(|fragment_id| self.factory.get_fragment(*fragment_id))
     }
 
     /// Gets all slots in this paperdoll.
@@ -112,9 +575,7 @@ impl PaperdollAsset {
             .get(&id)
             .map(|paperdoll| paperdoll.doll);
 
-        let doll = doll_id
-            .map(|doll_id| self.factory.get_doll(doll_id))
-            .flatten();
+        let doll = doll_id.and_then(|doll_id| self.factory.get_doll(doll_id));
 
         doll.map(|doll| {
             doll.slots
@@ -130,70 +591,594 @@ impl PaperdollAsset {
         self.id_to_texture.get(&id)
     }
 
-    /// Removes a paperdoll.
+    /// Checks if the given slot is drawn, i.e. not hidden by the fragment of another slot.
     ///
-    /// Returns the removed paperdoll if it previously existed, otherwise returns [`None`].
-    pub fn remove_paperdoll(&mut self, id: PaperdollId) -> Option<Paperdoll> {
-        let paperdoll = self.id_to_paperdoll.remove(&id);
-
-        self.id_to_texture.remove(&id);
-
-        paperdoll
+    /// A hidden slot keeps its fragment, see [`Self::get_slot_fragment`].
+    pub fn is_slot_visible(&self, id: PaperdollId, slot_id: u32) -> bool {
+        self.id_to_paperdoll
+            .get(&id)
+            .is_some_and(|paperdoll| !self.renderer().hidden_slots(paperdoll).contains(&slot_id))
     }
 
-    /// Sets the given slot to empty.
-    ///
-    /// # Errors
+    /// Links two fragments in different slots, so using or removing one of them also uses or
+    /// removes the other, eg. a pair of gloves.
     ///
-    /// - Will return an error if the slot is required.
-    pub fn slot_use_empty(&mut self, id: PaperdollId, slot_id: u32) -> Result<()> {
-        self.set_slot(id, slot_id, SetSlotBy::Empty)
-    }
-
-    /// Sets the given slot to the given fragment.
+    /// Cycling through the candidates with [`Self::slot_use_next`] or [`Self::slot_use_prev`]
+    /// switches linked fragments as a set.
     ///
     /// # Errors
     ///
-    /// - Will return an error if the fragment is not a candidate of this slot.
-    pub fn slot_use_fragment(
+    /// - Will return an error if the slots are the same.
+    /// - Will return an error if any of the fragments is not a candidate of its slot.
+    pub fn link_fragments(
         &mut self,
-        id: PaperdollId,
         slot_id: u32,
         fragment_id: u32,
+        partner_slot_id: u32,
+        partner_fragment_id: u32,
     ) -> Result<()> {
-        self.set_slot(id, slot_id, SetSlotBy::FragmentId(fragment_id))
+        if slot_id == partner_slot_id {
+            bail!("Can not link fragments in the same slot {}.", slot_id)
+        }
+
+        self.find_fragment_index_in_candidates(slot_id, fragment_id)?;
+        self.find_fragment_index_in_candidates(partner_slot_id, partner_fragment_id)?;
+
+        self.unlink_fragment(slot_id, fragment_id);
+        self.unlink_fragment(partner_slot_id, partner_fragment_id);
+
+        self.fragment_links.insert(
+            (slot_id, fragment_id),
+            (partner_slot_id, partner_fragment_id),
+        );
+        self.fragment_links.insert(
+            (partner_slot_id, partner_fragment_id),
+            (slot_id, fragment_id),
+        );
+
+        Ok(())
     }
 
-    /// Sets the given slot to the nth fragment of its candidates.
+    /// Merges the dolls, slots and fragments of a pack into this asset, eg. DLC or mod content.
+    ///
+    /// Slots and dolls of the pack are matched with the ones of this asset by name (their
+    /// descriptions): fragments of a matched slot are added to its candidates, and slots of a
+    /// matched doll are added to its slots. Unmatched or unnamed ones are added with new ids.
+    /// Fragments are always added with new ids, name clashes are reported as
+    /// [`crate::MergeConflict`]s.
+    ///
+    /// Existing paperdolls are not changed. Runtime data of the pack such as constraints and items
+    /// is not merged, use the returned id mappings to add them.
     ///
     /// # Errors
     ///
-    /// - Will return an error if the index is invalid.
-    pub fn slot_use_index(&mut self, id: PaperdollId, slot_id: u32, index: usize) -> Result<()> {
-        let fragment = self.find_fragment_in_candidates_by_index(slot_id, index)?;
+    /// - Will return an error if there are no ids left for the new dolls, slots or fragments.
+    pub fn merge(&mut self, pack: &PaperdollAsset) -> Result<MergeReport> {
+        merge::merge(
+            &mut self.factory,
+            &mut self.fragment_masks,
+            &pack.factory,
+            &pack.fragment_masks,
+        )
+    }
 
-        self.set_slot(id, slot_id, SetSlotBy::FragmentId(fragment.id()))
+    /// Starts building a paperdoll based on the given doll.
+    ///
+    /// ```no_run
+    /// # use bevy_paperdoll::PaperdollAsset;
+    /// # fn build(paperdoll_asset: &mut PaperdollAsset) -> anyhow::Result<()> {
+    /// let paperdoll_id = paperdoll_asset.paperdoll(0).with(0, 1).without(2).build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn paperdoll(&mut self, doll_id: u32) -> PaperdollBuilder<'_> {
+        PaperdollBuilder::new(self, doll_id)
     }
 
-    /// Sets the given slot to the next fragment of its candidates.
+    /// Iterates all paperdolls created from this asset, in no particular order.
+    pub fn paperdolls(&self) -> impl Iterator<Item = (PaperdollId, &Paperdoll)> {
+        self.id_to_paperdoll
+            .iter()
+            .map(|(id, paperdoll)| (*id, paperdoll))
+    }
+
+    /// Removes the configured default fragment of the given slot for the given doll.
+    pub fn remove_default_fragment(&mut self, doll_id: u32, slot_id: u32) -> Option<u32> {
+        self.slot_defaults.remove(&(doll_id, slot_id))
+    }
+
+    /// Removes a fragment along with its masks, variants, links and defaults.
     ///
-    /// If the current fragment is the last one, then:
+    /// The fragment is removed from constraints as well. Constraints left without fragments to
+    /// refer to are dropped.
     ///
-    /// - If the slot is required, set to the first fragment.
-    /// - If the slot is not required, set to empty.
+    /// Paperdolls using the fragment use the default fragments of the slots instead, see
+    /// [`Self::set_default_fragment`], and are re-rendered.
     ///
-    /// If it is an empty slot, set to the first fragment.
-    pub fn slot_use_next(&mut self, id: PaperdollId, slot_id: u32) -> Result<()> {
-        let fragment = self.get_slot_fragment(id, slot_id);
+    /// # Errors
+    ///
+    /// - Will return an error if the fragment does not exist.
+    pub fn remove_fragment(&mut self, fragment_id: u32) -> Result<()> {
+        self.get_fragment(fragment_id)?;
+
+        // Also removes the fragment from the candidates of all slots.
+        self.factory.remove_fragment(fragment_id);
+
+        self.fragment_clips.remove(&fragment_id);
+        self.fragment_hidden_slots.remove(&fragment_id);
+        self.fragment_masks.remove(&fragment_id);
+        self.fragment_variants.remove(&fragment_id);
+
+        for variants in self.fragment_variants.values_mut() {
+            variants.retain(|variant| {
+                let is_condition_used = match variant.condition {
+                    VariantCondition::SlotUsed(_) => false,
+                    VariantCondition::FragmentUsed(id) => id == fragment_id,
+                    VariantCondition::FragmentInSlot { fragment, .. } => fragment == fragment_id,
+                };
+
+                variant.fragment != fragment_id && !is_condition_used
+            });
+        }
 
-        let slot = self.get_slot(slot_id)?;
+        self.fragment_links
+            .retain(|(_, id), (_, partner_id)| *id != fragment_id && *partner_id != fragment_id);
 
-        let position = match fragment {
-            Some(fragment) => self.find_fragment_index_in_candidates(slot_id, fragment.id())?,
-            None => {
-                if slot.required {
-                    bail!("Slot {} has no valid fragment set.", slot_id)
-                }
+        self.slot_defaults.retain(|_, id| *id != fragment_id);
+
+        self.constraints.retain_mut(|constraint| match constraint {
+            Constraint::ExclusiveSlots(_) => true,
+            Constraint::ConflictsWithSlots { fragment, .. } => *fragment != fragment_id,
+            Constraint::ConflictsWithFragments {
+                fragment,
+                fragments,
+            }
+            | Constraint::Requires {
+                fragment,
+                fragments,
+            } => {
+                fragments.retain(|id| *id != fragment_id);
+
+                *fragment != fragment_id && !fragments.is_empty()
+            }
+        });
+
+        for item in self.items.values_mut() {
+            item.fragments.retain(|_, id| *id != fragment_id);
+        }
+
+        let mut used = vec![];
+
+        for (id, paperdoll) in &self.id_to_paperdoll {
+            for (slot_id, used_id) in &paperdoll.slot_map {
+                if *used_id == fragment_id {
+                    used.push((*id, *slot_id));
+                }
+            }
+        }
+
+        for (id, slot_id) in &used {
+            let default_id = self
+                .doll_of(*id)
+                .zip(self.factory.get_slot(*slot_id))
+                .and_then(|(doll_id, slot)| self.default_fragment(doll_id, slot));
+
+            if let Some(paperdoll) = self.id_to_paperdoll.get_mut(id) {
+                match default_id {
+                    Some(default_id) => paperdoll.slot_map.insert(*slot_id, default_id),
+                    None => paperdoll.slot_map.remove(slot_id),
+                };
+            }
+        }
+
+        let mut ids = used
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect::<Vec<PaperdollId>>();
+
+        ids.sort();
+        ids.dedup();
+
+        for id in ids {
+            self.update_texture(id)?;
+        }
+
+        Ok(())
+    }
+
+    /// Removes the mask image of the given fragment.
+    ///
+    /// Returns `true` if the fragment had a mask image.
+    pub fn remove_fragment_mask(&mut self, fragment_id: u32) -> Result<bool> {
+        let removed = self.fragment_masks.remove(&fragment_id).is_some();
+
+        if removed {
+            self.update_textures_using_fragment(fragment_id)?;
+        }
+
+        Ok(removed)
+    }
+
+    /// Removes the item with the given name.
+    ///
+    /// Paperdolls using the item keep its fragment.
+    pub fn remove_item(&mut self, name: &str) -> Option<Item> {
+        self.items.remove(name)
+    }
+
+    /// Removes a paperdoll.
+    ///
+    /// Returns the removed paperdoll if it previously existed, otherwise returns [`None`].
+    pub fn remove_paperdoll(&mut self, id: PaperdollId) -> Option<Paperdoll> {
+        let paperdoll = self.id_to_paperdoll.remove(&id);
+
+        self.id_to_modifiers.remove(&id);
+        self.id_to_texture.remove(&id);
+
+        paperdoll
+    }
+
+    /// Fixes the problems of the paperdoll, see [`Self::validate`].
+    ///
+    /// Unknown and non-candidate selections and slots not belonging to the doll are emptied, empty
+    /// required slots use their default fragments and constraints are resolved as with [`ConstraintPolicy::Resolve`].
+    ///
+    /// Returns the problems found before repairing.
+    ///
+    /// # Errors
+    ///
+    /// - Will return an error if the paperdoll does not exist or its doll is unknown.
+    /// - Will return an error if a required slot has no candidates.
+    /// - Will return an error if the constraints can not be satisfied.
+    pub fn repair(&mut self, id: PaperdollId) -> Result<Vec<OutfitProblem>> {
+        let paperdoll = self
+            .id_to_paperdoll
+            .get(&id)
+            .ok_or(anyhow!("Paperdoll with id '{}' not found.", id))?;
+
+        let problems = self.validate_outfit(paperdoll);
+
+        if problems.is_empty() {
+            return Ok(problems);
+        }
+
+        let doll = self
+            .factory
+            .get_doll(paperdoll.doll)
+            .ok_or(anyhow!("Doll with id '{}' not found.", paperdoll.doll))?;
+
+        let mut slot_map = HashMap::new();
+
+        for slot_id in &doll.slots {
+            let slot = self.get_slot(*slot_id)?;
+
+            let fragment_id = paperdoll
+                .slot_map
+                .get(slot_id)
+                .copied()
+                .filter(|fragment_id| {
+                    slot.candidates.contains(fragment_id)
+                        && self.factory.get_fragment(*fragment_id).is_some()
+                })
+                .or_else(|| {
+                    self.default_fragment(paperdoll.doll, slot)
+                        .filter(|_| slot.required)
+                });
+
+            match fragment_id {
+                Some(fragment_id) => {
+                    slot_map.insert(*slot_id, fragment_id);
+                }
+                None if slot.required => {
+                    bail!("Slot {} is required but has no candidates.", slot_id)
+                }
+                None => {}
+            }
+        }
+
+        let resolver = Resolver {
+            factory: &self.factory,
+            constraints: &self.constraints,
+            policy: ConstraintPolicy::Resolve,
+        };
+
+        let resolution = resolver.resolve(&doll.slots, slot_map, &[])?;
+
+        if let Some(paperdoll) = self.id_to_paperdoll.get_mut(&id) {
+            paperdoll.slot_map.clear();
+            paperdoll.slot_map.extend(resolution.slot_map);
+        }
+
+        self.update_texture(id)?;
+
+        Ok(problems)
+    }
+
+    /// Sets how slot changes breaking a constraint are handled.
+    ///
+    /// Defaults to [`ConstraintPolicy::Reject`].
+    pub fn set_constraint_policy(&mut self, policy: ConstraintPolicy) {
+        self.constraint_policy = policy;
+    }
+
+    /// Sets the fragment used by default in the given slot for the given doll.
+    ///
+    /// Defaults are used when creating paperdolls and by [`Self::slot_use_default`]. Without one,
+    /// required slots use their first candidate and other slots stay empty.
+    ///
+    /// # Errors
+    ///
+    /// - Will return an error if the slot does not belong to the doll.
+    /// - Will return an error if the fragment is not a candidate of the slot.
+    pub fn set_default_fragment(
+        &mut self,
+        doll_id: u32,
+        slot_id: u32,
+        fragment_id: u32,
+    ) -> Result<()> {
+        let doll = self
+            .factory
+            .get_doll(doll_id)
+            .ok_or(anyhow!("Doll with id '{}' not found.", doll_id))?;
+
+        if !doll.slots.contains(&slot_id) {
+            bail!("Slot {} does not belong to doll {}.", slot_id, doll_id)
+        }
+
+        self.find_fragment_index_in_candidates(slot_id, fragment_id)?;
+
+        self.slot_defaults.insert((doll_id, slot_id), fragment_id);
+
+        Ok(())
+    }
+
+    /// Sets the default fragments of all dolls in the given defaults asset.
+    ///
+    /// # Errors
+    ///
+    /// - Will return an error if any of the defaults is invalid, see
+    ///   [`Self::set_default_fragment`]. Defaults before the invalid one are still set.
+    pub fn set_default_fragments(&mut self, defaults: &PaperdollDefaults) -> Result<()> {
+        for (doll_id, slots) in &defaults.dolls {
+            for (slot_id, fragment_id) in slots {
+                self.set_default_fragment(*doll_id, *slot_id, *fragment_id)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sets the slots hidden while the given fragment is used, eg. a full-face helmet hiding the
+    /// hair.
+    ///
+    /// The fragments of hidden slots are kept and drawn again once the fragment is no longer used.
+    /// Pass an empty list to remove the rule.
+    ///
+    /// # Errors
+    ///
+    /// - Will return an error if the fragment or any of the slots does not exist.
+    pub fn set_fragment_hidden_slots(
+        &mut self,
+        fragment_id: u32,
+        slot_ids: Vec<u32>,
+    ) -> Result<()> {
+        self.get_fragment(fragment_id)?;
+
+        for slot_id in &slot_ids {
+            self.get_slot(*slot_id)?;
+        }
+
+        if slot_ids.is_empty() {
+            self.fragment_hidden_slots.remove(&fragment_id);
+        } else {
+            self.fragment_hidden_slots.insert(fragment_id, slot_ids);
+        }
+
+        self.update_textures_using_fragment(fragment_id)
+    }
+
+    /// Sets the mask image of the given fragment.
+    ///
+    /// The red, green and blue channels of the mask mark the regions tinted by the
+    /// [`MaskColors`] of the slot the fragment is used in.
+    ///
+    /// Mask images are also loaded from the paperdoll file, from entries named after the image of
+    /// the fragment, e.g. `fragment_0.mask.png` for `fragment_0.png`.
+    ///
+    /// # Errors
+    ///
+    /// - Will return an error if the fragment does not exist.
+    /// - Will return an error if the pixels of the image can not be read.
+    pub fn set_fragment_mask(&mut self, fragment_id: u32, mask: &Image) -> Result<()> {
+        self.get_fragment(fragment_id)?;
+
+        let mask = render::image_data_from_image(mask)?;
+
+        self.fragment_masks.insert(fragment_id, mask);
+
+        self.update_textures_using_fragment(fragment_id)
+    }
+
+    /// Sets the palette swap applied to the doll and all fragments of the paperdoll.
+    ///
+    /// Applied after the palette swap of the slot, if any.
+    pub fn set_palette_swap(&mut self, id: PaperdollId, palette_swap: PaletteSwap) -> Result<()> {
+        self.modifiers_mut(id)?.palette_swap = Some(palette_swap);
+
+        self.update_texture(id)
+    }
+
+    /// Overrides the blend of the given slot for this paperdoll only.
+    ///
+    /// # Errors
+    ///
+    /// - Will return an error if the slot does not exist.
+    pub fn set_paperdoll_slot_blend(
+        &mut self,
+        id: PaperdollId,
+        slot_id: u32,
+        blend: SlotBlend,
+    ) -> Result<()> {
+        self.get_slot(slot_id)?;
+
+        self.modifiers_mut(id)?.slot_blends.insert(slot_id, blend);
+
+        self.update_texture(id)
+    }
+
+    /// Sets the blend mode and opacity of the given slot for all paperdolls.
+    ///
+    /// Slots use [`SlotBlend::default`] unless set, which matches regular alpha compositing. See
+    /// [`Self::set_paperdoll_slot_blend`] for overriding it for a single paperdoll.
+    ///
+    /// # Errors
+    ///
+    /// - Will return an error if the slot does not exist.
+    pub fn set_slot_blend(&mut self, slot_id: u32, blend: SlotBlend) -> Result<()> {
+        self.get_slot(slot_id)?;
+
+        if blend.is_default() {
+            self.slot_blends.remove(&slot_id);
+        } else {
+            self.slot_blends.insert(slot_id, blend);
+        }
+
+        self.update_textures_using_slot(slot_id)
+    }
+
+    /// Sets the colors tinting the masked regions of the fragment used in the given slot.
+    ///
+    /// # Errors
+    ///
+    /// - Will return an error if the slot does not exist.
+    pub fn set_slot_mask_colors(
+        &mut self,
+        id: PaperdollId,
+        slot_id: u32,
+        mask_colors: MaskColors,
+    ) -> Result<()> {
+        self.get_slot(slot_id)?;
+
+        self.modifiers_mut(id)?
+            .slot_mask_colors
+            .insert(slot_id, mask_colors);
+
+        self.update_texture(id)
+    }
+
+    /// Sets the palette swap applied to the fragment used in the given slot.
+    ///
+    /// # Errors
+    ///
+    /// - Will return an error if the slot does not exist.
+    pub fn set_slot_palette_swap(
+        &mut self,
+        id: PaperdollId,
+        slot_id: u32,
+        palette_swap: PaletteSwap,
+    ) -> Result<()> {
+        self.get_slot(slot_id)?;
+
+        self.modifiers_mut(id)?
+            .slot_palette_swaps
+            .insert(slot_id, palette_swap);
+
+        self.update_texture(id)
+    }
+
+    /// Gets the fragment id of every slot in this paperdoll, in the order the slots are drawn.
+    ///
+    /// Empty slots have no fragment id.
+    pub fn slot_map(&self, id: PaperdollId) -> Option<Vec<(u32, Option<u32>)>> {
+        let paperdoll = self.id_to_paperdoll.get(&id)?;
+
+        let doll = self.factory.get_doll(paperdoll.doll)?;
+
+        Some(
+            doll.slots
+                .iter()
+                .map(|slot_id| (*slot_id, paperdoll.slot_map.get(slot_id).copied()))
+                .collect(),
+        )
+    }
+
+    /// Sets the given slot to its default fragment, see [`Self::set_default_fragment`].
+    ///
+    /// # Errors
+    ///
+    /// - Will return an error if the slot has no default and is required.
+    pub fn slot_use_default(&mut self, id: PaperdollId, slot_id: u32) -> Result<()> {
+        let doll_id = self
+            .doll_of(id)
+            .ok_or(anyhow!("Paperdoll with id '{}' not found.", id))?;
+
+        let slot = self.get_slot(slot_id)?;
+
+        match self.default_fragment(doll_id, slot) {
+            Some(fragment_id) => self.set_slot(id, slot_id, SetSlotBy::FragmentId(fragment_id)),
+            None => self.set_slot(id, slot_id, SetSlotBy::Empty),
+        }
+    }
+
+    /// Sets the given slot to empty.
+    ///
+    /// # Errors
+    ///
+    /// - Will return an error if the slot is required.
+    /// - Will return an error if the change breaks a constraint which can not be resolved.
+    pub fn slot_use_empty(&mut self, id: PaperdollId, slot_id: u32) -> Result<()> {
+        self.set_slot(id, slot_id, SetSlotBy::Empty)
+    }
+
+    /// Sets the given slot to the given fragment.
+    ///
+    /// # Errors
+    ///
+    /// - Will return an error if the fragment is not a candidate of this slot.
+    /// - Will return an error if the change breaks a constraint which can not be resolved.
+    pub fn slot_use_fragment(
+        &mut self,
+        id: PaperdollId,
+        slot_id: u32,
+        fragment_id: u32,
+    ) -> Result<()> {
+        self.set_slot(id, slot_id, SetSlotBy::FragmentId(fragment_id))
+    }
+
+    /// Sets the given slot to the nth fragment of its candidates.
+    ///
+    /// # Errors
+    ///
+    /// - Will return an error if the index is invalid.
+    pub fn slot_use_index(&mut self, id: PaperdollId, slot_id: u32, index: usize) -> Result<()> {
+        let fragment = self.find_fragment_in_candidates_by_index(slot_id, index)?;
+
+        self.set_slot(id, slot_id, SetSlotBy::FragmentId(fragment.id()))
+    }
+
+    /// Sets the given slot to the next fragment of its candidates.
+    ///
+    /// If the current fragment is the last one, then:
+    ///
+    /// - If the slot is required, set to the first fragment.
+    /// - If the slot is not required, set to empty.
+    ///
+    /// If it is an empty slot, set to the first fragment.
+    ///
+    /// # Errors
+    ///
+    /// - Will return an error if the slot is required but empty, see [`Self::repair`].
+    pub fn slot_use_next(&mut self, id: PaperdollId, slot_id: u32) -> Result<()> {
+        let fragment = self.get_slot_fragment(id, slot_id);
+
+        let slot = self.get_slot(slot_id)?;
+
+        let position = match fragment {
+            Some(fragment) => self.find_fragment_index_in_candidates(slot_id, fragment.id())?,
+            None => {
+                if slot.required {
+                    bail!("Slot {} has no valid fragment set.", slot_id)
+                }
 
                 usize::MAX
             }
@@ -222,6 +1207,10 @@ impl PaperdollAsset {
     /// - If the slot is not required, set to empty.
     ///
     /// If it is an empty slot, set to the last fragment.
+    ///
+    /// # Errors
+    ///
+    /// - Will return an error if the slot is required but empty, see [`Self::repair`].
     pub fn slot_use_prev(&mut self, id: PaperdollId, slot_id: u32) -> Result<()> {
         let fragment = self.get_slot_fragment(id, slot_id);
 
@@ -258,18 +1247,349 @@ impl PaperdollAsset {
         self.id_to_texture.remove(&id)
     }
 
-    fn find_fragment_in_candidates_by_index(
-        &self,
-        slot_id: u32,
-        index: usize,
-    ) -> Result<&Fragment> {
-        let slot = self.get_slot(slot_id)?;
-
-        let fragment_id = slot.candidates.iter().nth(index).ok_or(anyhow!(
-            "Index out of range: '{}' in candidates of slot {}.",
-            index,
-            slot_id
-        ))?;
+    /// Gets the manifest describing the dolls, slots and fragments of this asset.
+    ///
+    /// Image paths are kept as loaded, so images without paths are not referred to, see
+    /// [`Self::write_ppd`].
+    pub fn to_manifest(&self) -> Manifest {
+        self.factory.to_manifest()
+    }
+
+    /// Uses the items of one paperdoll in another one, picking the fragments for the doll of the
+    /// target paperdoll.
+    ///
+    /// Items are used in the order of their names. Returns the names of the items having no
+    /// fragment for the doll of the target paperdoll.
+    ///
+    /// # Errors
+    ///
+    /// - Will return an error if any of the paperdolls does not exist.
+    /// - Will return an error if an item can not be used, see [`Self::use_item`]. The target
+    ///   paperdoll is left unchanged.
+    pub fn transfer_items(&mut self, from: PaperdollId, to: PaperdollId) -> Result<Vec<String>> {
+        if !self.id_to_paperdoll.contains_key(&to) {
+            bail!("Paperdoll with id '{}' not found.", to)
+        }
+
+        if !self.id_to_paperdoll.contains_key(&from) {
+            bail!("Paperdoll with id '{}' not found.", from)
+        }
+
+        let names = self
+            .get_items(from)
+            .into_iter()
+            .map(|name| name.to_owned())
+            .collect::<Vec<String>>();
+
+        let original = self.id_to_paperdoll[&to].slot_map.clone();
+
+        let mut missing = vec![];
+
+        for name in names {
+            if self.item_fragment(to, &name).is_err() {
+                missing.push(name);
+
+                continue;
+            }
+
+            if let Err(err) = self.use_item(to, &name) {
+                if let Some(paperdoll) = self.id_to_paperdoll.get_mut(&to) {
+                    paperdoll.slot_map.clear();
+                    paperdoll.slot_map.extend(original);
+                }
+
+                self.update_texture(to)?;
+
+                return Err(err);
+            }
+        }
+
+        Ok(missing)
+    }
+
+    /// Removes the link of the fragment in the given slot, see [`Self::link_fragments`].
+    ///
+    /// Returns the partner slot and fragment if the fragment was linked.
+    pub fn unlink_fragment(&mut self, slot_id: u32, fragment_id: u32) -> Option<(u32, u32)> {
+        let partner = self.fragment_links.remove(&(slot_id, fragment_id));
+
+        if let Some(partner) = partner {
+            self.fragment_links.remove(&partner);
+        }
+
+        partner
+    }
+
+    /// Uses the fragment of the item for the doll of this paperdoll, in the first slot accepting
+    /// it.
+    ///
+    /// # Errors
+    ///
+    /// - Will return an error if the item does not exist or has no fragment for this doll.
+    /// - Will return an error if no slot of this doll accepts the fragment.
+    pub fn use_item(&mut self, id: PaperdollId, name: &str) -> Result<()> {
+        let (slot_id, fragment_id) = self.item_fragment(id, name)?;
+
+        self.set_slot(id, slot_id, SetSlotBy::FragmentId(fragment_id))
+    }
+
+    /// Gets the problems of the paperdoll, see [`Self::validate_outfit`].
+    ///
+    /// Returns [`None`] if the paperdoll does not exist.
+    pub fn validate(&self, id: PaperdollId) -> Option<Vec<OutfitProblem>> {
+        self.id_to_paperdoll
+            .get(&id)
+            .map(|paperdoll| self.validate_outfit(paperdoll))
+    }
+
+    /// Gets the problems of the selections, in the order the slots are drawn.
+    ///
+    /// Slots which do not belong to the doll are reported last. An empty list means the
+    /// selections are valid.
+    pub fn validate_outfit(&self, outfit: &Paperdoll) -> Vec<OutfitProblem> {
+        let Some(doll) = self.factory.get_doll(outfit.doll) else {
+            return vec![OutfitProblem::UnknownDoll(outfit.doll)];
+        };
+
+        let mut problems = vec![];
+
+        for slot_id in &doll.slots {
+            let Some(slot) = self.factory.get_slot(*slot_id) else {
+                problems.push(OutfitProblem::UnknownSlot(*slot_id));
+
+                continue;
+            };
+
+            match outfit.slot_map.get(slot_id) {
+                Some(fragment_id) if self.factory.get_fragment(*fragment_id).is_none() => {
+                    problems.push(OutfitProblem::UnknownFragment {
+                        slot: *slot_id,
+                        fragment: *fragment_id,
+                    });
+                }
+                Some(fragment_id) if !slot.candidates.contains(fragment_id) => {
+                    problems.push(OutfitProblem::NotCandidate {
+                        slot: *slot_id,
+                        fragment: *fragment_id,
+                    });
+                }
+                Some(_) => {}
+                None if slot.required => {
+                    problems.push(OutfitProblem::MissingRequiredSlot(*slot_id));
+                }
+                None => {}
+            }
+        }
+
+        let mut foreign_slots = outfit
+            .slot_map
+            .keys()
+            .filter(|slot_id| !doll.slots.contains(*slot_id))
+            .copied()
+            .collect::<Vec<u32>>();
+
+        foreign_slots.sort();
+
+        problems.extend(foreign_slots.into_iter().map(|slot_id| {
+            if self.factory.get_slot(slot_id).is_some() {
+                OutfitProblem::SlotNotInDoll(slot_id)
+            } else {
+                OutfitProblem::UnknownSlot(slot_id)
+            }
+        }));
+
+        let resolver = Resolver {
+            factory: &self.factory,
+            constraints: &self.constraints,
+            policy: ConstraintPolicy::Reject,
+        };
+
+        let slot_map = outfit
+            .slot_map
+            .iter()
+            .map(|(slot_id, fragment_id)| (*slot_id, *fragment_id))
+            .collect();
+
+        for broken in resolver.breaks(&slot_map) {
+            problems.push(OutfitProblem::ConstraintViolation {
+                constraint: self.constraints[broken.constraint].clone(),
+                slots: broken.slots(),
+            });
+        }
+
+        problems
+    }
+
+    /// Writes the dolls, slots, fragments and fragment masks of this asset as a manifest
+    /// (`<name>.paperdoll.ron`) with the images next to it, which can be loaded again.
+    ///
+    /// See [`Self::write_ppd`] for the images written.
+    ///
+    /// # Errors
+    ///
+    /// - Will return an error if any image path is absolute or leaves the directory.
+    /// - Will return an error if any image can not be encoded or any file can not be written.
+    pub fn write_manifest_dir(&self, dir: impl AsRef<Path>, name: &str) -> Result<()> {
+        Export::new(&self.factory, &self.fragment_masks)?.write_manifest_dir(dir.as_ref(), name)
+    }
+
+    /// Writes the dolls, slots, fragments and fragment masks of this asset as a paperdoll file
+    /// (.ppd), which can be loaded again.
+    ///
+    /// Images are written as PNG. Images without paths are named after their ids, eg.
+    /// `fragment_1.png`. Runtime data such as paperdolls, constraints and items is not written.
+    ///
+    /// # Errors
+    ///
+    /// - Will return an error if any image can not be encoded or the writer fails.
+    pub fn write_ppd<W: Write>(&self, writer: W) -> Result<()> {
+        Export::new(&self.factory, &self.fragment_masks)?.write_ppd(writer)
+    }
+
+    pub(crate) fn build_paperdoll(
+        &mut self,
+        doll_id: u32,
+        selections: &[(u32, Option<u32>)],
+        fill: SlotFill,
+    ) -> Result<PaperdollId> {
+        let doll = self
+            .factory
+            .get_doll(doll_id)
+            .ok_or(anyhow!("Doll with id '{}' not found.", doll_id))?;
+
+        let mut changes: Vec<(u32, Option<u32>)> = vec![];
+
+        for (slot_id, fragment_id) in selections {
+            if !doll.slots.contains(slot_id) {
+                bail!("Slot {} does not belong to doll {}.", slot_id, doll_id)
+            }
+
+            let set_slot_by = match fragment_id {
+                Some(fragment_id) => SetSlotBy::FragmentId(*fragment_id),
+                None => SetSlotBy::Empty,
+            };
+
+            let fragment_id = self.check_slot_change(*slot_id, &set_slot_by)?;
+
+            changes.retain(|(id, _)| id != slot_id);
+            changes.push((*slot_id, fragment_id));
+        }
+
+        for slot_id in &doll.slots {
+            if changes.iter().any(|(id, _)| id == slot_id) {
+                continue;
+            }
+
+            let slot = self.get_slot(*slot_id)?;
+
+            if fill == SlotFill::Defaults {
+                if let Some(fragment_id) = self.default_fragment(doll_id, slot) {
+                    changes.push((*slot_id, Some(fragment_id)));
+
+                    continue;
+                }
+            }
+
+            if !slot.required {
+                continue;
+            }
+
+            let fragment_id = match fill {
+                SlotFill::Defaults | SlotFill::FirstCandidate => slot.candidates.first(),
+                SlotFill::LastCandidate => slot.candidates.last(),
+                SlotFill::Strict => bail!("Slot {} is required.", slot_id),
+            };
+
+            let fragment_id = fragment_id.ok_or(anyhow!(
+                "Slot {} is required but has no candidates.",
+                slot_id
+            ))?;
+
+            changes.push((*slot_id, Some(*fragment_id)));
+        }
+
+        let resolver = Resolver {
+            factory: &self.factory,
+            constraints: &self.constraints,
+            policy: ConstraintPolicy::Reject,
+        };
+
+        let resolution = resolver.resolve(&doll.slots, HashMap::new(), &changes)?;
+
+        let mut paperdoll = self.factory.builder().doll(doll_id).build();
+
+        paperdoll.slot_map.extend(resolution.slot_map);
+
+        let id = get_id();
+
+        self.id_to_paperdoll.insert(id, paperdoll);
+
+        if let Err(err) = self.update_texture(id) {
+            self.id_to_paperdoll.remove(&id);
+
+            return Err(err);
+        }
+
+        Ok(id)
+    }
+
+    pub(crate) fn with_diagnostics(mut self, diagnostics: Vec<Diagnostic>) -> Self {
+        self.diagnostics = diagnostics;
+
+        self
+    }
+
+    pub(crate) fn with_fragment_masks(mut self, fragment_masks: HashMap<u32, ImageData>) -> Self {
+        self.fragment_masks = fragment_masks;
+
+        self
+    }
+
+    fn check_slot_change(&self, slot_id: u32, set_slot_by: &SetSlotBy) -> Result<Option<u32>> {
+        let slot = self.get_slot(slot_id)?;
+
+        match set_slot_by {
+            SetSlotBy::Empty => {
+                if slot.required {
+                    bail!("Slot {} cannot be empty.", slot_id)
+                }
+
+                Ok(None)
+            }
+            SetSlotBy::FragmentId(fragment_id) => {
+                if !slot.candidates.contains(fragment_id) {
+                    bail!(
+                        "Slot {} does not accept fragment {} as a candidate.",
+                        slot_id,
+                        fragment_id
+                    )
+                }
+
+                Ok(Some(*fragment_id))
+            }
+        }
+    }
+
+    fn default_fragment(&self, doll_id: u32, slot: &Slot) -> Option<u32> {
+        match self.slot_defaults.get(&(doll_id, slot.id())) {
+            Some(fragment_id) => Some(*fragment_id),
+            None if slot.required => slot.candidates.first().copied(),
+            None => None,
+        }
+    }
+
+    fn find_fragment_in_candidates_by_index(
+        &self,
+        slot_id: u32,
+        index: usize,
+    ) -> Result<&Fragment> {
+        let slot = self.get_slot(slot_id)?;
+
+        let fragment_id = slot.candidates.get(index).ok_or(anyhow!(
+            "Index out of range: '{}' in candidates of slot {}.",
+            index,
+            slot_id
+        ))?;
 
         self.get_fragment(*fragment_id)
     }
@@ -299,38 +1619,239 @@ impl PaperdollAsset {
             .ok_or(anyhow!("Slot with id '{}' not found.", slot_id))
     }
 
-    fn set_slot(&mut self, id: PaperdollId, slot_id: u32, set_slot_by: SetSlotBy) -> Result<()> {
-        if !self.id_to_paperdoll.contains_key(&id) {
-            bail!("Paperdoll with id '{}' not found.", id)
+    /// Gets the slot and fragment used for the item in this paperdoll.
+    fn item_fragment(&self, id: PaperdollId, name: &str) -> Result<(u32, u32)> {
+        let paperdoll = self
+            .id_to_paperdoll
+            .get(&id)
+            .ok_or(anyhow!("Paperdoll with id '{}' not found.", id))?;
+
+        let item = self
+            .items
+            .get(name)
+            .ok_or(anyhow!("Item with name '{}' not found.", name))?;
+
+        let fragment_id = *item.fragments.get(&paperdoll.doll).ok_or(anyhow!(
+            "Item '{}' has no fragment for doll {}.",
+            name,
+            paperdoll.doll
+        ))?;
+
+        let slot_id = self
+            .get_slots(id)
+            .into_iter()
+            .find(|slot| slot.candidates.contains(&fragment_id))
+            .map(|slot| slot.id())
+            .ok_or(anyhow!(
+                "No slot of doll {} accepts fragment {} of item '{}'.",
+                paperdoll.doll,
+                fragment_id,
+                name
+            ))?;
+
+        Ok((slot_id, fragment_id))
+    }
+
+    /// Gets the slot change along with the changes of the linked partners.
+    fn linked_changes(
+        &self,
+        id: PaperdollId,
+        slot_id: u32,
+        fragment_id: Option<u32>,
+    ) -> Result<Vec<(u32, Option<u32>)>> {
+        let paperdoll = self
+            .id_to_paperdoll
+            .get(&id)
+            .ok_or(anyhow!("Paperdoll with id '{}' not found.", id))?;
+
+        let doll_slots = self
+            .factory
+            .get_doll(paperdoll.doll)
+            .map(|doll| doll.slots.as_slice())
+            .unwrap_or_default();
+
+        let mut changes = vec![(slot_id, fragment_id)];
+
+        let partner = fragment_id
+            .and_then(|fragment_id| self.fragment_links.get(&(slot_id, fragment_id)))
+            .filter(|(partner_slot_id, _)| doll_slots.contains(partner_slot_id));
+
+        if let Some((partner_slot_id, partner_fragment_id)) = partner {
+            changes.push((*partner_slot_id, Some(*partner_fragment_id)));
         }
 
-        let slot = self.get_slot(slot_id)?;
+        let previous_partner = paperdoll
+            .slot_map
+            .get(&slot_id)
+            .filter(|previous_id| Some(**previous_id) != fragment_id)
+            .and_then(|previous_id| self.fragment_links.get(&(slot_id, *previous_id)));
+
+        if let Some((partner_slot_id, partner_fragment_id)) = previous_partner {
+            let is_partner_used =
+                paperdoll.slot_map.get(partner_slot_id) == Some(partner_fragment_id);
+            let is_partner_replaced =
+                partner.is_some_and(|(slot_id, _)| slot_id == partner_slot_id);
+            let is_partner_required = self
+                .factory
+                .get_slot(*partner_slot_id)
+                .is_some_and(|slot| slot.required);
+
+            if is_partner_used && !is_partner_replaced && !is_partner_required {
+                changes.push((*partner_slot_id, None));
+            }
+        }
 
-        match set_slot_by {
-            SetSlotBy::Empty => {
-                if slot.required {
-                    bail!("Slot {} cannot be empty.", slot_id)
-                }
+        Ok(changes)
+    }
+
+    /// Maps the selections of the paperdoll to the given doll, replacing the fragments of items
+    /// and keeping the other fragments of the slots shared by both dolls.
+    ///
+    /// Returns the new slot map and the dropped slots and fragments.
+    fn map_selections(&self, paperdoll: &Paperdoll, doll_id: u32) -> Result<MappedSelections> {
+        let doll = self
+            .factory
+            .get_doll(doll_id)
+            .ok_or(anyhow!("Doll with id '{}' not found.", doll_id))?;
+
+        let mut selections = paperdoll
+            .slot_map
+            .iter()
+            .map(|(slot_id, fragment_id)| (*slot_id, *fragment_id))
+            .collect::<Vec<(u32, u32)>>();
+
+        selections.sort();
+
+        let item_fragment = |fragment_id: u32| {
+            self.items
+                .values()
+                .find(|item| item.fragments.get(&paperdoll.doll) == Some(&fragment_id))
+                .and_then(|item| item.fragments.get(&doll_id))
+                .copied()
+        };
 
-                if let Some(paperdoll) = self.id_to_paperdoll.get_mut(&id) {
-                    paperdoll.slot_map.remove(&slot_id);
+        let mut slot_map = HashMap::new();
+
+        let mut dropped = vec![];
+
+        let mut item_selections = vec![];
+
+        for (slot_id, fragment_id) in selections {
+            match item_fragment(fragment_id) {
+                Some(item_fragment_id) => {
+                    item_selections.push((slot_id, fragment_id, item_fragment_id))
+                }
+                None if doll.slots.contains(&slot_id) => {
+                    slot_map.insert(slot_id, fragment_id);
                 }
+                None => dropped.push((slot_id, fragment_id)),
             }
-            SetSlotBy::FragmentId(fragment_id) => {
-                let slot = self.get_slot(slot_id)?;
+        }
 
-                if !slot.candidates.contains(&fragment_id) {
-                    bail!(
-                        "Slot {} does not accept fragment {} as a candidate.",
-                        slot_id,
-                        fragment_id
-                    )
+        for (slot_id, fragment_id, item_fragment_id) in item_selections {
+            let accepts = |slot_id: &u32| {
+                !slot_map.contains_key(slot_id)
+                    && self
+                        .factory
+                        .get_slot(*slot_id)
+                        .is_some_and(|slot| slot.candidates.contains(&item_fragment_id))
+            };
+
+            // Prefers the same slot when the doll shares it.
+            let item_slot_id = Some(slot_id)
+                .filter(|slot_id| doll.slots.contains(slot_id) && accepts(slot_id))
+                .or_else(|| doll.slots.iter().copied().find(accepts));
+
+            match item_slot_id {
+                Some(item_slot_id) => {
+                    slot_map.insert(item_slot_id, item_fragment_id);
                 }
+                None => dropped.push((slot_id, fragment_id)),
+            }
+        }
 
-                if let Some(paperdoll) = self.id_to_paperdoll.get_mut(&id) {
-                    paperdoll.slot_map.insert(slot_id, fragment_id);
-                }
+        for slot_id in &doll.slots {
+            if slot_map.contains_key(slot_id) {
+                continue;
+            }
+
+            let Some(slot) = self.factory.get_slot(*slot_id) else {
+                continue;
+            };
+
+            if !slot.required {
+                continue;
             }
+
+            if let Some(fragment_id) = self.default_fragment(doll_id, slot) {
+                slot_map.insert(*slot_id, fragment_id);
+            }
+        }
+
+        Ok((slot_map, dropped))
+    }
+
+    fn modifiers_mut(&mut self, id: PaperdollId) -> Result<&mut Modifiers> {
+        if !self.id_to_paperdoll.contains_key(&id) {
+            bail!("Paperdoll with id '{}' not found.", id)
+        }
+
+        Ok(self.id_to_modifiers.entry(id).or_default())
+    }
+
+    fn renderer(&self) -> Renderer<'_> {
+        Renderer {
+            factory: &self.factory,
+            fragment_clips: &self.fragment_clips,
+            fragment_hidden_slots: &self.fragment_hidden_slots,
+            fragment_masks: &self.fragment_masks,
+            fragment_variants: &self.fragment_variants,
+            slot_blends: &self.slot_blends,
+        }
+    }
+
+    fn resolve(
+        &self,
+        id: PaperdollId,
+        changes: &[(u32, Option<u32>)],
+        policy: ConstraintPolicy,
+    ) -> Result<Resolution> {
+        let paperdoll = self
+            .id_to_paperdoll
+            .get(&id)
+            .ok_or(anyhow!("Paperdoll with id '{}' not found.", id))?;
+
+        let doll_slots = self
+            .factory
+            .get_doll(paperdoll.doll)
+            .map(|doll| doll.slots.as_slice())
+            .unwrap_or_default();
+
+        let slot_map = paperdoll
+            .slot_map
+            .iter()
+            .map(|(slot_id, fragment_id)| (*slot_id, *fragment_id))
+            .collect();
+
+        let resolver = Resolver {
+            factory: &self.factory,
+            constraints: &self.constraints,
+            policy,
+        };
+
+        resolver.resolve(doll_slots, slot_map, changes)
+    }
+
+    fn set_slot(&mut self, id: PaperdollId, slot_id: u32, set_slot_by: SetSlotBy) -> Result<()> {
+        let fragment_id = self.check_slot_change(slot_id, &set_slot_by)?;
+
+        let changes = self.linked_changes(id, slot_id, fragment_id)?;
+
+        let resolution = self.resolve(id, &changes, self.constraint_policy)?;
+
+        if let Some(paperdoll) = self.id_to_paperdoll.get_mut(&id) {
+            paperdoll.slot_map.clear();
+            paperdoll.slot_map.extend(resolution.slot_map);
         }
 
         self.update_texture(id)?;
@@ -340,7 +1861,9 @@ impl PaperdollAsset {
 
     fn update_texture(&mut self, id: PaperdollId) -> Result<()> {
         if let Some(paperdoll) = self.id_to_paperdoll.get(&id) {
-            let texture = self.factory.render_paperdoll(&paperdoll)?;
+            let texture = self
+                .renderer()
+                .render(paperdoll, self.id_to_modifiers.get(&id))?;
 
             let image = Image::new(
                 Extent3d {
@@ -361,6 +1884,40 @@ impl PaperdollAsset {
 
         bail!("Paperdoll with id '{}' not found.", id)
     }
+
+    fn update_textures_using_fragment(&mut self, fragment_id: u32) -> Result<()> {
+        let ids = self
+            .id_to_paperdoll
+            .iter()
+            .filter(|(_, paperdoll)| paperdoll.slot_map.values().any(|id| *id == fragment_id))
+            .map(|(id, _)| *id)
+            .collect::<Vec<PaperdollId>>();
+
+        for id in ids {
+            self.update_texture(id)?;
+        }
+
+        Ok(())
+    }
+
+    fn update_textures_using_slot(&mut self, slot_id: u32) -> Result<()> {
+        let ids = self
+            .id_to_paperdoll
+            .iter()
+            .filter(|(_, paperdoll)| {
+                self.factory
+                    .get_doll(paperdoll.doll)
+                    .is_some_and(|doll| doll.slots.contains(&slot_id))
+            })
+            .map(|(id, _)| *id)
+            .collect::<Vec<PaperdollId>>();
+
+        for id in ids {
+            self.update_texture(id)?;
+        }
+
+        Ok(())
+    }
 }
 
 fn get_id() -> PaperdollId {
@@ -368,3 +1925,575 @@ fn get_id() -> PaperdollId {
 
     ID.fetch_add(1, Ordering::Relaxed)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        asset_builder::{PaperdollAssetBuilder, SlotOptions},
+        blend::BlendMode,
+    };
+
+    fn basic() -> PaperdollAsset {
+        PaperdollAsset::from_bytes(include_bytes!("../assets/basic.ppd")).unwrap()
+    }
+
+    /// Creates a doll with three slots drawn from bottom to top, each with its own fragment as
+    /// the only candidate.
+    fn layered() -> (PaperdollAsset, Vec<u32>, Vec<u32>) {
+        let mut builder = PaperdollAssetBuilder::new();
+
+        let doll_id = builder.add_doll(UVec2::splat(4), "Body").unwrap();
+
+        let mut slots = vec![];
+        let mut fragments = vec![];
+
+        for desc in ["Bottom", "Middle", "Top"] {
+            let slot_id = builder
+                .add_slot(
+                    doll_id,
+                    SlotOptions {
+                        desc: desc.to_owned(),
+                        size: UVec2::splat(4),
+                        ..default()
+                    },
+                )
+                .unwrap();
+
+            let fragment_id = builder
+                .add_fragment_from_rgba(
+                    UVec2::splat(4),
+                    vec![255; 64],
+                    Vec2::ZERO,
+                    desc,
+                    &[slot_id],
+                )
+                .unwrap();
+
+            slots.push(slot_id);
+            fragments.push(fragment_id);
+        }
+
+        (builder.build().unwrap(), slots, fragments)
+    }
+
+    /// Creates a slim and a broad doll sharing a hat slot, with a vest item drawn by a slot of
+    /// each doll and a cape slot only on the slim doll.
+    ///
+    /// Returns the slots and fragments of the hat, slim vest, broad vest and cape.
+    fn body_types() -> (PaperdollAsset, [u32; 4], [u32; 4]) {
+        let mut builder = PaperdollAssetBuilder::new();
+
+        let slim = builder.add_doll(UVec2::splat(4), "Slim").unwrap();
+        let broad = builder.add_doll(UVec2::splat(4), "Broad").unwrap();
+
+        let mut slots = [0; 4];
+        let mut fragments = [0; 4];
+
+        for (index, (doll_id, desc)) in [
+            (slim, "Hat"),
+            (slim, "Slim vest"),
+            (broad, "Broad vest"),
+            (slim, "Cape"),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let slot_id = builder.add_slot(doll_id, default()).unwrap();
+
+            slots[index] = slot_id;
+            fragments[index] = builder
+                .add_fragment_from_rgba(UVec2::ONE, vec![255; 4], Vec2::ZERO, desc, &[slot_id])
+                .unwrap();
+        }
+
+        let mut asset = builder.build().unwrap();
+
+        if let Some(doll) = asset.factory.get_doll_mut(broad) {
+            doll.slots.push(slots[0]);
+        }
+
+        asset
+            .add_item(
+                Item::new("Vest")
+                    .with_fragment(slim, fragments[1])
+                    .with_fragment(broad, fragments[2]),
+            )
+            .unwrap();
+
+        (asset, slots, fragments)
+    }
+
+    /// Gets the fragment used in the slot of the paperdoll.
+    fn used(asset: &PaperdollAsset, id: PaperdollId, slot_id: u32) -> Option<u32> {
+        asset.get_slot_fragment(id, slot_id).map(Fragment::id)
+    }
+
+    /// Gets paperdolls using each candidate of each slot, with required slots filled.
+    fn outfits(asset: &PaperdollAsset) -> Vec<Paperdoll> {
+        let mut outfits = vec![];
+
+        for (doll_id, doll) in asset.factory.dolls() {
+            let mut base = asset.factory.builder().doll(*doll_id).build();
+
+            for slot_id in &doll.slots {
+                let slot = asset.factory.get_slot(*slot_id).unwrap();
+
+                if let Some(fragment_id) = slot.candidates.first() {
+                    base.slot_map.insert(*slot_id, *fragment_id);
+                }
+            }
+
+            for slot_id in &doll.slots {
+                let slot = asset.factory.get_slot(*slot_id).unwrap();
+
+                for fragment_id in &slot.candidates {
+                    let mut paperdoll = asset.factory.builder().doll(*doll_id).build();
+
+                    paperdoll.slot_map.extend(base.slot_map.clone());
+                    paperdoll.slot_map.insert(*slot_id, *fragment_id);
+
+                    outfits.push(paperdoll);
+                }
+            }
+
+            outfits.push(base);
+        }
+
+        outfits
+    }
+
+    #[test]
+    fn composite_matches_factory_render() {
+        let asset = basic();
+
+        let renderer = asset.renderer();
+
+        for paperdoll in outfits(&asset) {
+            let expected = asset.factory.render_paperdoll(&paperdoll).unwrap();
+            let actual = renderer
+                .composite(&paperdoll, &Modifiers::default())
+                .unwrap();
+
+            assert_eq!(actual.width, expected.width);
+            assert_eq!(actual.height, expected.height);
+            assert!(actual.pixels == expected.pixels, "{:?}", paperdoll.slot_map);
+        }
+    }
+
+    #[test]
+    fn default_slot_blends_match_factory_render() {
+        let asset = basic();
+
+        let renderer = asset.renderer();
+
+        let slot_blends = asset
+            .factory
+            .slots()
+            .map(|(slot_id, _)| {
+                (
+                    *slot_id,
+                    SlotBlend {
+                        mode: BlendMode::Normal,
+                        opacity: 1.0,
+                    },
+                )
+            })
+            .collect::<HashMap<u32, SlotBlend>>();
+
+        let modifiers = Modifiers {
+            slot_blends,
+            ..Default::default()
+        };
+
+        for paperdoll in outfits(&asset) {
+            let expected = asset.factory.render_paperdoll(&paperdoll).unwrap();
+            let actual = renderer.render(&paperdoll, Some(&modifiers)).unwrap();
+
+            assert!(actual.pixels == expected.pixels, "{:?}", paperdoll.slot_map);
+        }
+    }
+
+    #[test]
+    fn hidden_slots_do_not_hide() {
+        let (mut asset, s, g) = layered();
+
+        let id = asset.create_paperdoll(0);
+
+        for (slot_id, fragment_id) in s.iter().zip(&g) {
+            asset.slot_use_fragment(id, *slot_id, *fragment_id).unwrap();
+        }
+
+        asset.set_fragment_hidden_slots(g[1], vec![s[0]]).unwrap();
+        asset.set_fragment_hidden_slots(g[2], vec![s[1]]).unwrap();
+
+        assert!(asset.is_slot_visible(id, s[0]));
+        assert!(!asset.is_slot_visible(id, s[1]));
+        assert!(asset.is_slot_visible(id, s[2]));
+
+        asset.slot_use_empty(id, s[2]).unwrap();
+
+        assert!(!asset.is_slot_visible(id, s[0]));
+        assert!(asset.is_slot_visible(id, s[1]));
+    }
+
+    #[test]
+    fn items_are_sorted_by_name() {
+        let (mut asset, _, g) = layered();
+
+        for (name, fragment_id) in [("Vest", g[0]), ("Belt", g[1]), ("Hat", g[2])] {
+            asset
+                .add_item(Item::new(name).with_fragment(0, fragment_id))
+                .unwrap();
+        }
+
+        let from = asset.create_paperdoll(0);
+        let to = asset.create_paperdoll(0);
+
+        for name in ["Hat", "Vest", "Belt"] {
+            asset.use_item(from, name).unwrap();
+        }
+
+        assert_eq!(asset.get_items(from), vec!["Belt", "Hat", "Vest"]);
+
+        assert!(asset.transfer_items(from, to).unwrap().is_empty());
+        assert_eq!(asset.get_items(to), vec!["Belt", "Hat", "Vest"]);
+    }
+
+    #[test]
+    fn validate_outfit_reports_foreign_slots() {
+        let mut builder = PaperdollAssetBuilder::new();
+
+        let doll_id = builder.add_doll(UVec2::splat(4), "Body").unwrap();
+        let other_doll_id = builder.add_doll(UVec2::splat(4), "Other").unwrap();
+
+        let other_slot_id = builder.add_slot(other_doll_id, default()).unwrap();
+
+        let fragment_id = builder
+            .add_fragment_from_rgba(
+                UVec2::ONE,
+                vec![255; 4],
+                Vec2::ZERO,
+                "Dot",
+                &[other_slot_id],
+            )
+            .unwrap();
+
+        let mut asset = builder.build().unwrap();
+
+        let mut outfit = asset.factory.builder().doll(doll_id).build();
+
+        outfit.slot_map.insert(other_slot_id, fragment_id);
+        outfit.slot_map.insert(99, fragment_id);
+
+        assert_eq!(
+            asset.validate_outfit(&outfit),
+            vec![
+                OutfitProblem::SlotNotInDoll(other_slot_id),
+                OutfitProblem::UnknownSlot(99),
+            ]
+        );
+
+        let id = asset.create_paperdoll(doll_id);
+
+        if let Some(paperdoll) = asset.id_to_paperdoll.get_mut(&id) {
+            paperdoll.slot_map.insert(other_slot_id, fragment_id);
+        }
+
+        assert_eq!(
+            asset.repair(id).unwrap(),
+            vec![OutfitProblem::SlotNotInDoll(other_slot_id)]
+        );
+        assert!(asset.validate(id).unwrap().is_empty());
+    }
+
+    #[test]
+    fn merged_asset_reloads_from_ppd() {
+        let mut asset = basic();
+        let pack = basic();
+
+        let report = asset.merge(&pack).unwrap();
+
+        let mut bytes = vec![];
+
+        asset.write_ppd(&mut bytes).unwrap();
+
+        let reloaded = PaperdollAsset::from_bytes(&bytes).unwrap();
+
+        assert_eq!(
+            reloaded.factory.fragments().count(),
+            asset.factory.fragments().count()
+        );
+
+        for (pack_id, id) in &report.fragments {
+            let expected = pack.factory.get_fragment(*pack_id).unwrap();
+            let actual = reloaded.factory.get_fragment(*id).unwrap();
+
+            assert_eq!(actual.desc, expected.desc);
+            assert!(actual.image.pixels == expected.image.pixels, "{}", id);
+        }
+
+        for id in report.slots.values() {
+            assert_eq!(
+                reloaded.factory.get_slot(*id).unwrap().candidates,
+                asset.factory.get_slot(*id).unwrap().candidates
+            );
+        }
+    }
+
+    #[test]
+    fn linked_fragments_are_used_together() {
+        let (mut asset, s, g) = layered();
+
+        asset.link_fragments(s[0], g[0], s[2], g[2]).unwrap();
+
+        let id = asset.create_paperdoll(0);
+
+        asset.slot_use_fragment(id, s[0], g[0]).unwrap();
+
+        assert_eq!(used(&asset, id, s[2]), Some(g[2]));
+
+        asset.slot_use_empty(id, s[2]).unwrap();
+
+        assert_eq!(used(&asset, id, s[0]), None);
+
+        assert_eq!(asset.unlink_fragment(s[2], g[2]), Some((s[0], g[0])));
+
+        asset.slot_use_fragment(id, s[0], g[0]).unwrap();
+
+        assert_eq!(used(&asset, id, s[2]), None);
+    }
+
+    #[test]
+    fn variant_drawn_while_condition_met() {
+        let (mut asset, s, g) = layered();
+
+        asset
+            .add_fragment_variant(
+                g[0],
+                FragmentVariant {
+                    condition: VariantCondition::SlotUsed(s[2]),
+                    fragment: g[1],
+                },
+            )
+            .unwrap();
+
+        let id = asset.create_paperdoll(0);
+
+        asset.slot_use_fragment(id, s[0], g[0]).unwrap();
+
+        let drawn =
+            |asset: &PaperdollAsset| asset.get_slot_drawn_fragment(id, s[0]).map(Fragment::id);
+
+        assert_eq!(drawn(&asset), Some(g[0]));
+
+        asset.slot_use_fragment(id, s[2], g[2]).unwrap();
+
+        assert_eq!(drawn(&asset), Some(g[1]));
+        assert_eq!(used(&asset, id, s[0]), Some(g[0]));
+    }
+
+    #[test]
+    fn change_doll_keeps_shared_slots_and_maps_items() {
+        let (mut asset, s, g) = body_types();
+
+        let id = asset.create_paperdoll(0);
+
+        for index in [0, 1, 3] {
+            asset.slot_use_fragment(id, s[index], g[index]).unwrap();
+        }
+
+        assert_eq!(asset.change_doll(id, 1).unwrap(), vec![(s[3], g[3])]);
+
+        assert_eq!(asset.doll_of(id), Some(1));
+        assert_eq!(used(&asset, id, s[0]), Some(g[0]));
+        assert_eq!(used(&asset, id, s[2]), Some(g[2]));
+        assert_eq!(used(&asset, id, s[1]), None);
+    }
+
+    #[test]
+    fn clone_and_copy_outfit() {
+        let (mut asset, s, g) = body_types();
+
+        let slim = asset.create_paperdoll(0);
+        let broad = asset.create_paperdoll(1);
+
+        for index in [0, 1, 3] {
+            asset.slot_use_fragment(slim, s[index], g[index]).unwrap();
+        }
+
+        let clone = asset.clone_paperdoll(slim).unwrap();
+
+        assert_ne!(clone, slim);
+        assert_eq!(asset.slot_map(clone), asset.slot_map(slim));
+
+        asset.slot_use_empty(clone, s[0]).unwrap();
+
+        assert_eq!(used(&asset, slim, s[0]), Some(g[0]));
+
+        assert_eq!(asset.copy_outfit(slim, broad).unwrap(), vec![(s[3], g[3])]);
+
+        assert_eq!(asset.doll_of(broad), Some(1));
+        assert_eq!(
+            asset.slot_map(broad),
+            Some(vec![(s[2], Some(g[2])), (s[0], Some(g[0]))])
+        );
+    }
+
+    #[test]
+    fn inspect_paperdolls() {
+        let (mut asset, s, g) = layered();
+
+        let first = asset.create_paperdoll(0);
+        let second = asset.create_paperdoll(0);
+
+        asset.slot_use_fragment(first, s[1], g[1]).unwrap();
+
+        let mut ids = asset.paperdolls().map(|(id, _)| id).collect::<Vec<_>>();
+
+        ids.sort();
+
+        let mut expected = vec![first, second];
+
+        expected.sort();
+
+        assert_eq!(ids, expected);
+
+        assert_eq!(
+            asset.get_paperdoll(first).map(|paperdoll| paperdoll.doll),
+            Some(0)
+        );
+        assert_eq!(asset.doll_of(second), Some(0));
+        assert_eq!(
+            asset.slot_map(first),
+            Some(vec![(s[0], None), (s[1], Some(g[1])), (s[2], None)])
+        );
+
+        asset.remove_paperdoll(second);
+
+        assert_eq!(asset.doll_of(second), None);
+        assert_eq!(asset.slot_map(second), None);
+    }
+
+    #[test]
+    fn paperdoll_builder_validates_selections() {
+        let (mut asset, s, g) = layered();
+
+        if let Some(slot) = asset.factory.get_slot_mut(s[2]) {
+            slot.required = true;
+        }
+
+        assert!(asset.paperdoll(9).build().is_err());
+        assert!(asset.paperdoll(0).with(s[0], g[1]).build().is_err());
+        assert!(asset.paperdoll(0).fill(SlotFill::Strict).build().is_err());
+        assert!(asset.paperdoll(0).without(s[2]).build().is_err());
+
+        let id = asset.paperdoll(0).with(s[0], g[0]).build().unwrap();
+
+        assert_eq!(
+            asset.slot_map(id),
+            Some(vec![(s[0], Some(g[0])), (s[1], None), (s[2], Some(g[2]))])
+        );
+    }
+
+    #[test]
+    fn default_fragments_from_sidecar() {
+        let (mut asset, s, g) = layered();
+
+        if let Some(slot) = asset.factory.get_slot_mut(s[0]) {
+            slot.required = true;
+            slot.candidates.push(g[1]);
+        }
+
+        assert_eq!(asset.get_default_fragment(0, s[0]), Some(g[0]));
+        assert_eq!(asset.get_default_fragment(0, s[1]), None);
+
+        let defaults: PaperdollDefaults =
+            ron::de::from_str(&format!("(dolls: {{ 0: {{ {}: {} }} }})", s[0], g[1])).unwrap();
+
+        asset.set_default_fragments(&defaults).unwrap();
+
+        assert!(asset.set_default_fragment(0, s[1], g[0]).is_err());
+
+        let id = asset.create_paperdoll(0);
+
+        assert_eq!(used(&asset, id, s[0]), Some(g[1]));
+
+        asset.slot_use_index(id, s[0], 0).unwrap();
+        asset.slot_use_default(id, s[0]).unwrap();
+
+        assert_eq!(used(&asset, id, s[0]), Some(g[1]));
+
+        assert_eq!(asset.remove_default_fragment(0, s[0]), Some(g[1]));
+        assert_eq!(asset.get_default_fragment(0, s[0]), Some(g[0]));
+    }
+
+    #[test]
+    fn built_asset_reloads_from_ppd() {
+        let (asset, s, g) = layered();
+
+        let mut bytes = vec![];
+
+        asset.write_ppd(&mut bytes).unwrap();
+
+        let reloaded = PaperdollAsset::from_bytes(&bytes).unwrap();
+
+        for (slot_id, fragment_id) in s.iter().zip(&g) {
+            let expected = asset.factory.get_fragment(*fragment_id).unwrap();
+            let actual = reloaded.factory.get_fragment(*fragment_id).unwrap();
+
+            assert_eq!(actual.desc, expected.desc);
+            assert_eq!(actual.image.pixels, expected.image.pixels);
+            assert_eq!(
+                reloaded.factory.get_slot(*slot_id).unwrap().candidates,
+                vec![*fragment_id]
+            );
+        }
+    }
+
+    #[test]
+    fn add_and_remove_fragment_at_runtime() {
+        let (mut asset, s, g) = layered();
+
+        let image = Image::new(
+            Extent3d {
+                width: 2,
+                height: 2,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            vec![255; 16],
+            TextureFormat::Rgba8UnormSrgb,
+            RenderAssetUsages::default(),
+        );
+
+        assert!(asset
+            .add_fragment(&image, Vec2::ZERO, "Decal", &[99])
+            .is_err());
+
+        let fragment_id = asset
+            .add_fragment(&image, Vec2::ZERO, "Decal", &[s[0]])
+            .unwrap();
+
+        assert!(!g.contains(&fragment_id));
+
+        let id = asset.create_paperdoll(0);
+
+        asset.slot_use_fragment(id, s[0], fragment_id).unwrap();
+
+        assert_eq!(used(&asset, id, s[0]), Some(fragment_id));
+
+        asset.remove_fragment(fragment_id).unwrap();
+
+        assert_eq!(used(&asset, id, s[0]), None);
+        assert_eq!(
+            asset
+                .get_fragments_by_slot(s[0])
+                .iter()
+                .map(|fragment| fragment.id())
+                .collect::<Vec<u32>>(),
+            vec![g[0]]
+        );
+        assert!(asset.remove_fragment(fragment_id).is_err());
+    }
+}
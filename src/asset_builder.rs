@@ -0,0 +1,383 @@
+use anyhow::{bail, Result};
+use bevy::prelude::*;
+use paperdoll_tar::paperdoll::{ColorType, ImageData, PaperdollFactory, Point};
+
+use crate::{
+    asset::PaperdollAsset,
+    lint::{self, Severity},
+    render,
+};
+
+/// A builder declaring the dolls, slots and fragments of a [`PaperdollAsset`] in code, eg. for
+/// procedural content or tests.
+///
+/// The builder starts without dolls, so ids of dolls, slots and fragments all start from `0`.
+///
+/// ```no_run
+/// # use bevy::prelude::*;
+/// # use bevy_paperdoll::{PaperdollAssetBuilder, SlotOptions};
+/// #
+/// # fn build() -> anyhow::Result<()> {
+/// let mut builder = PaperdollAssetBuilder::new();
+///
+/// let doll = builder.add_doll(UVec2::splat(32), "Body")?;
+///
+/// let slot = builder.add_slot(
+///     doll,
+///     SlotOptions {
+///         desc: "Hat".to_owned(),
+///         size: UVec2::splat(32),
+///         ..default()
+///     },
+/// )?;
+///
+/// let pixels = vec![255; 32 * 32 * 4];
+///
+/// builder.add_fragment_from_rgba(UVec2::splat(32), pixels, Vec2::ZERO, "Cap", &[slot])?;
+///
+/// let asset = builder.build()?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct PaperdollAssetBuilder {
+    factory: PaperdollFactory,
+}
+
+impl Default for PaperdollAssetBuilder {
+    fn default() -> Self {
+        let mut factory = PaperdollFactory::default();
+
+        // The factory is created with an empty doll, which is not declared by the user.
+        let doll_ids = factory.dolls().map(|(id, _)| *id).collect::<Vec<u32>>();
+
+        for doll_id in doll_ids {
+            factory.remove_doll(doll_id);
+        }
+
+        Self { factory }
+    }
+}
+
+/// The properties of a slot added by [`PaperdollAssetBuilder::add_slot`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SlotOptions {
+    /// The name of the slot.
+    pub desc: String,
+    /// The positions of the slot in pixels of the doll, the fragment is drawn at each of them.
+    /// The slot is drawn at the origin if empty.
+    pub positions: Vec<Vec2>,
+    /// The point aligned with the pivots of fragments, relative to each position.
+    pub anchor: Vec2,
+    pub size: UVec2,
+    /// Whether fragments fill the slot, ignoring anchor and pivots.
+    pub constrained: bool,
+    /// Whether the slot can not be empty.
+    pub required: bool,
+    /// The index of the slot in the draw order of the doll, later slots are drawn above. The slot
+    /// is drawn above the other slots if [`None`] or out of range.
+    pub z_order: Option<usize>,
+}
+
+impl PaperdollAssetBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a doll of the given size, without an image.
+    ///
+    /// Returns the id of the new doll.
+    ///
+    /// # Errors
+    ///
+    /// - Will return an error if there are no doll ids left.
+    pub fn add_doll(&mut self, size: UVec2, desc: impl Into<String>) -> Result<u32> {
+        let doll_id = self.factory.add_doll()?;
+
+        if let Some(doll) = self.factory.get_doll_mut(doll_id) {
+            doll.desc = desc.into();
+            doll.width = size.x;
+            doll.height = size.y;
+        }
+
+        Ok(doll_id)
+    }
+
+    /// Adds a doll drawn with the given RGBA pixels as its background, sized after them.
+    ///
+    /// Returns the id of the new doll.
+    ///
+    /// # Errors
+    ///
+    /// - Will return an error if the image is empty or its size does not match its pixels.
+    /// - Will return an error if there are no doll ids left.
+    pub fn add_doll_from_rgba(
+        &mut self,
+        size: UVec2,
+        pixels: Vec<u8>,
+        desc: impl Into<String>,
+    ) -> Result<u32> {
+        let image = rgba_image(size, pixels)?;
+
+        self.insert_doll(image, desc.into())
+    }
+
+    /// Adds a doll drawn with the given image as its background, sized after it.
+    ///
+    /// Returns the id of the new doll.
+    ///
+    /// # Errors
+    ///
+    /// - Will return an error if the image is empty or its pixels can not be read.
+    /// - Will return an error if there are no doll ids left.
+    pub fn add_doll_with_image(&mut self, image: &Image, desc: impl Into<String>) -> Result<u32> {
+        let image = render::image_data_from_image(image)?;
+
+        self.insert_doll(image, desc.into())
+    }
+
+    /// Adds a fragment drawn with the given image, as a candidate of the given slots.
+    ///
+    /// The pivot is in pixels of the image.
+    ///
+    /// Returns the id of the new fragment.
+    ///
+    /// # Errors
+    ///
+    /// - Will return an error if the image is empty or its pixels can not be read.
+    /// - Will return an error if any of the slots does not exist.
+    pub fn add_fragment(
+        &mut self,
+        image: &Image,
+        pivot: Vec2,
+        desc: impl Into<String>,
+        slots: &[u32],
+    ) -> Result<u32> {
+        let image = render::image_data_from_image(image)?;
+
+        self.insert_fragment(image, pivot, desc.into(), slots)
+    }
+
+    /// Adds a fragment drawn with the given RGBA pixels, see [`Self::add_fragment`].
+    ///
+    /// # Errors
+    ///
+    /// - Will return an error if the image is empty or its size does not match its pixels.
+    /// - Will return an error if any of the slots does not exist.
+    pub fn add_fragment_from_rgba(
+        &mut self,
+        size: UVec2,
+        pixels: Vec<u8>,
+        pivot: Vec2,
+        desc: impl Into<String>,
+        slots: &[u32],
+    ) -> Result<u32> {
+        let image = rgba_image(size, pixels)?;
+
+        self.insert_fragment(image, pivot, desc.into(), slots)
+    }
+
+    /// Adds a slot to the given doll.
+    ///
+    /// Returns the id of the new slot.
+    ///
+    /// # Errors
+    ///
+    /// - Will return an error if the doll does not exist.
+    /// - Will return an error if there are no slot ids left.
+    pub fn add_slot(&mut self, doll_id: u32, options: SlotOptions) -> Result<u32> {
+        if self.factory.get_doll(doll_id).is_none() {
+            bail!("Doll with id '{}' not found.", doll_id)
+        }
+
+        let slot_id = self.factory.add_slot()?;
+
+        if let Some(slot) = self.factory.get_slot_mut(slot_id) {
+            slot.desc = options.desc;
+            slot.anchor = Point {
+                x: options.anchor.x,
+                y: options.anchor.y,
+            };
+            slot.width = options.size.x;
+            slot.height = options.size.y;
+            slot.constrainted = options.constrained;
+            slot.required = options.required;
+
+            if !options.positions.is_empty() {
+                slot.positions = options
+                    .positions
+                    .iter()
+                    .map(|position| Point {
+                        x: position.x,
+                        y: position.y,
+                    })
+                    .collect();
+            }
+        }
+
+        if let Some(doll) = self.factory.get_doll_mut(doll_id) {
+            let index = options
+                .z_order
+                .map_or(doll.slots.len(), |z_order| z_order.min(doll.slots.len()));
+
+            doll.slots.insert(index, slot_id);
+        }
+
+        Ok(slot_id)
+    }
+
+    /// Creates the asset.
+    ///
+    /// # Errors
+    ///
+    /// - Will return an error if the asset is invalid, eg. a required slot has no candidates.
+    ///   Warnings are kept in [`PaperdollAsset::diagnostics`].
+    pub fn build(self) -> Result<PaperdollAsset> {
+        let diagnostics = lint::lint(&self.factory);
+
+        if let Some(diagnostic) = diagnostics
+            .iter()
+            .find(|diagnostic| diagnostic.severity == Severity::Error)
+        {
+            bail!("{}", diagnostic.lint)
+        }
+
+        Ok(PaperdollAsset::new(self.factory).with_diagnostics(diagnostics))
+    }
+
+    fn insert_doll(&mut self, image: ImageData, desc: String) -> Result<u32> {
+        let doll_id = self.add_doll(UVec2::new(image.width, image.height), desc)?;
+
+        if let Some(doll) = self.factory.get_doll_mut(doll_id) {
+            doll.image = image;
+        }
+
+        Ok(doll_id)
+    }
+
+    fn insert_fragment(
+        &mut self,
+        image: ImageData,
+        pivot: Vec2,
+        desc: String,
+        slots: &[u32],
+    ) -> Result<u32> {
+        for slot_id in slots {
+            if self.factory.get_slot(*slot_id).is_none() {
+                bail!("Slot with id '{}' not found.", slot_id)
+            }
+        }
+
+        let fragment_id = self.factory.add_fragment()?;
+
+        if let Some(fragment) = self.factory.get_fragment_mut(fragment_id) {
+            fragment.desc = desc;
+            fragment.image = image;
+            fragment.pivot = Point {
+                x: pivot.x,
+                y: pivot.y,
+            };
+        }
+
+        for slot_id in slots {
+            if let Some(slot) = self.factory.get_slot_mut(*slot_id) {
+                if !slot.candidates.contains(&fragment_id) {
+                    slot.candidates.push(fragment_id);
+                }
+            }
+        }
+
+        Ok(fragment_id)
+    }
+}
+
+fn rgba_image(size: UVec2, pixels: Vec<u8>) -> Result<ImageData> {
+    if size.x == 0 || size.y == 0 {
+        bail!("Image is empty.")
+    }
+
+    let len = size.x as usize * size.y as usize * 4;
+
+    if pixels.len() != len {
+        bail!(
+            "Image of {}x{} has {} bytes of pixels, expected {}.",
+            size.x,
+            size.y,
+            pixels.len(),
+            len
+        )
+    }
+
+    Ok(ImageData {
+        width: size.x,
+        height: size.y,
+        color_type: ColorType::Rgba,
+        pixels,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids_start_from_zero_and_slots_follow_z_order() {
+        let mut builder = PaperdollAssetBuilder::new();
+
+        let doll_id = builder
+            .add_doll_from_rgba(UVec2::ONE, vec![255; 4], "Body")
+            .unwrap();
+
+        let top = builder.add_slot(doll_id, SlotOptions::default()).unwrap();
+        let bottom = builder
+            .add_slot(
+                doll_id,
+                SlotOptions {
+                    z_order: Some(0),
+                    ..default()
+                },
+            )
+            .unwrap();
+
+        let fragment_id = builder
+            .add_fragment_from_rgba(UVec2::ONE, vec![255; 4], Vec2::ZERO, "Dot", &[top])
+            .unwrap();
+
+        assert_eq!((doll_id, top, bottom, fragment_id), (0, 0, 1, 0));
+
+        let asset = builder.build().unwrap();
+
+        let dolls = asset.get_dolls();
+
+        assert_eq!(dolls.len(), 1);
+        assert_eq!(dolls[0].slots, vec![bottom, top]);
+        assert_eq!(dolls[0].image.pixels, vec![255; 4]);
+    }
+
+    #[test]
+    fn invalid_declarations_fail() {
+        let mut builder = PaperdollAssetBuilder::new();
+
+        assert!(builder.add_slot(0, SlotOptions::default()).is_err());
+        assert!(builder
+            .add_doll_from_rgba(UVec2::splat(2), vec![255; 4], "Body")
+            .is_err());
+
+        let doll_id = builder.add_doll(UVec2::splat(2), "Body").unwrap();
+
+        assert!(builder
+            .add_fragment_from_rgba(UVec2::ONE, vec![255; 4], Vec2::ZERO, "Dot", &[0])
+            .is_err());
+
+        builder
+            .add_slot(
+                doll_id,
+                SlotOptions {
+                    required: true,
+                    ..default()
+                },
+            )
+            .unwrap();
+
+        assert!(builder.build().is_err());
+    }
+}
@@ -0,0 +1,94 @@
+/// How the fragments of a slot are combined with the layers below.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum BlendMode {
+    /// Regular alpha compositing.
+    #[default]
+    Normal,
+    Multiply,
+    Screen,
+    Additive,
+    Overlay,
+}
+
+impl BlendMode {
+    fn blend(&self, backdrop: f32, source: f32) -> f32 {
+        match self {
+            Self::Normal => source,
+            Self::Multiply => backdrop * source,
+            Self::Screen => backdrop + source - backdrop * source,
+            Self::Additive => (backdrop + source).min(1.0),
+            Self::Overlay => {
+                if backdrop <= 0.5 {
+                    2.0 * backdrop * source
+                } else {
+                    1.0 - 2.0 * (1.0 - backdrop) * (1.0 - source)
+                }
+            }
+        }
+    }
+}
+
+/// Blend mode and opacity of a slot.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SlotBlend {
+    pub mode: BlendMode,
+    /// Opacity of the fragments in the slot, from `0.0` to `1.0`.
+    pub opacity: f32,
+}
+
+impl Default for SlotBlend {
+    fn default() -> Self {
+        Self {
+            mode: BlendMode::Normal,
+            opacity: 1.0,
+        }
+    }
+}
+
+impl SlotBlend {
+    pub(crate) fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Composites the source pixel onto the backdrop pixel.
+    ///
+    /// Uses the same integer math as [`paperdoll_tar::paperdoll::PaperdollFactory::render`], so
+    /// [`BlendMode::Normal`] at full opacity gives the same pixels.
+    pub(crate) fn composite(&self, backdrop: [u8; 4], source: [u8; 4]) -> [u8; 4] {
+        let source_alpha = if self.opacity >= 1.0 {
+            source[3]
+        } else {
+            (source[3] as f32 * self.opacity.max(0.0)) as u8
+        };
+
+        let alpha = source_alpha
+            .saturating_add((backdrop[3] as f32 * (1.0 - source_alpha as f32 / 255.0)) as u8);
+
+        let mut color = backdrop;
+
+        color[3] = alpha;
+
+        if alpha == 0 {
+            return color;
+        }
+
+        let sa = source_alpha as f32 / 255.0;
+        let da = backdrop[3] as f32 / 255.0;
+        let a = alpha as f32 / 255.0;
+
+        for (channel, value) in color.iter_mut().take(3).enumerate() {
+            let cs = source[channel] as f32;
+            let cb = backdrop[channel] as f32;
+
+            // Where both are opaque, the source color is replaced by the blended one.
+            let mixed = match self.mode {
+                BlendMode::Normal => cs,
+                mode => cs * (1.0 - da) + mode.blend(cb / 255.0, cs / 255.0) * 255.0 * da,
+            };
+
+            *value = ((mixed * sa + cb * da * (1.0 - sa)) / a) as u8;
+        }
+
+        color
+    }
+}
@@ -0,0 +1,75 @@
+use anyhow::Result;
+
+use crate::{PaperdollAsset, PaperdollId};
+
+/// How slots not set explicitly are filled when building a paperdoll.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum SlotFill {
+    /// Slots use their default fragments, see [`PaperdollAsset::set_default_fragment`].
+    #[default]
+    Defaults,
+    /// Required slots use their first candidate, others stay empty.
+    FirstCandidate,
+    /// Required slots use their last candidate, others stay empty.
+    LastCandidate,
+    /// All slots not set stay empty, building fails if any of them is required.
+    Strict,
+}
+
+/// Builder for creating a paperdoll with explicit initial selections.
+///
+/// Created by [`PaperdollAsset::paperdoll`].
+pub struct PaperdollBuilder<'a> {
+    asset: &'a mut PaperdollAsset,
+    doll_id: u32,
+    fill: SlotFill,
+    selections: Vec<(u32, Option<u32>)>,
+}
+
+impl<'a> PaperdollBuilder<'a> {
+    pub(crate) fn new(asset: &'a mut PaperdollAsset, doll_id: u32) -> Self {
+        Self {
+            asset,
+            doll_id,
+            fill: SlotFill::default(),
+            selections: vec![],
+        }
+    }
+
+    /// Creates the paperdoll.
+    ///
+    /// Returns the id used to refer to this paperdoll for later usage.
+    ///
+    /// # Errors
+    ///
+    /// - Will return an error if the doll does not exist.
+    /// - Will return an error if a slot does not belong to the doll.
+    /// - Will return an error if a fragment is not a candidate of its slot.
+    /// - Will return an error if a required slot is left empty.
+    /// - Will return an error if the selections break a constraint.
+    pub fn build(self) -> Result<PaperdollId> {
+        self.asset
+            .build_paperdoll(self.doll_id, &self.selections, self.fill)
+    }
+
+    /// Sets how slots not set explicitly are filled.
+    pub fn fill(mut self, fill: SlotFill) -> Self {
+        self.fill = fill;
+
+        self
+    }
+
+    /// Sets the given slot to the given fragment.
+    pub fn with(mut self, slot_id: u32, fragment_id: u32) -> Self {
+        self.selections.push((slot_id, Some(fragment_id)));
+
+        self
+    }
+
+    /// Leaves the given slot empty.
+    pub fn without(mut self, slot_id: u32) -> Self {
+        self.selections.push((slot_id, None));
+
+        self
+    }
+}
@@ -0,0 +1,28 @@
+use bevy::prelude::*;
+
+/// The area of a fragment hiding the pixels of other slots.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ClipRegion {
+    /// All non-transparent pixels of the fragment.
+    Fragment,
+    /// A rectangle in pixels of the fragment image, inclusive on both ends.
+    Rect(URect),
+}
+
+/// The slots a clip mask applies to.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ClipTarget {
+    /// All slots drawn below the slot of the fragment.
+    Below,
+    /// The given slots.
+    Slots(Vec<u32>),
+}
+
+/// A mask declared by a fragment, hiding the pixels of other slots while the fragment is used.
+///
+/// eg. A hat cutting the hair poking through its brim.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClipMask {
+    pub region: ClipRegion,
+    pub target: ClipTarget,
+}
@@ -0,0 +1,528 @@
+use anyhow::{bail, Result};
+use bevy::platform::collections::{HashMap, HashSet};
+use paperdoll_tar::paperdoll::PaperdollFactory;
+
+/// A rule restricting which fragments can be used together.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Constraint {
+    /// At most one of the slots can be used at the same time, eg. a dress and a top.
+    ExclusiveSlots(Vec<u32>),
+    /// The fragment can not be used while any of the slots is used, eg. a two-handed weapon and
+    /// the off-hand slot.
+    ConflictsWithSlots { fragment: u32, slots: Vec<u32> },
+    /// The fragment can not be used with any of the other fragments.
+    ConflictsWithFragments { fragment: u32, fragments: Vec<u32> },
+    /// The fragment can only be used while at least one of the other fragments is used.
+    Requires { fragment: u32, fragments: Vec<u32> },
+}
+
+/// How a slot change breaking a [`Constraint`] is handled.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum ConstraintPolicy {
+    /// The change fails with an error.
+    #[default]
+    Reject,
+    /// Conflicting slots are emptied and required fragments are used automatically.
+    Resolve,
+}
+
+/// The slot map after a change, with constraints resolved.
+pub(crate) struct Resolution {
+    pub(crate) slot_map: HashMap<u32, u32>,
+    /// Slots emptied to satisfy the constraints.
+    pub(crate) displaced: Vec<u32>,
+}
+
+enum Violation {
+    Displace(u32, String),
+    Require(u32, Vec<u32>),
+}
+
+/// A constraint broken by the slots of a slot map.
+pub(crate) struct Break {
+    /// The index of the constraint.
+    pub(crate) constraint: usize,
+    pub(crate) kind: BreakKind,
+}
+
+pub(crate) enum BreakKind {
+    /// The fragments of the slots can not be used together.
+    Conflict(u32, u32, String),
+    /// The fragment of the slot requires one of the fragments, none of which is used.
+    Missing(u32, u32, Vec<u32>),
+}
+
+impl Break {
+    /// Gets the slots involved, sorted.
+    pub(crate) fn slots(&self) -> Vec<u32> {
+        let mut slots = match &self.kind {
+            BreakKind::Conflict(a, b, _) => vec![*a, *b],
+            BreakKind::Missing(slot_id, _, _) => vec![*slot_id],
+        };
+
+        slots.sort();
+
+        slots
+    }
+
+    fn key(&self) -> (usize, Vec<u32>) {
+        (self.constraint, self.slots())
+    }
+}
+
+pub(crate) struct Resolver<'a> {
+    pub(crate) factory: &'a PaperdollFactory,
+    pub(crate) constraints: &'a [Constraint],
+    pub(crate) policy: ConstraintPolicy,
+}
+
+impl Resolver<'_> {
+    /// Applies the changes to the slot map, then resolves the constraints according to the policy.
+    ///
+    /// Slots in the changes are never displaced. Constraints already broken before the changes
+    /// are ignored, unless they involve the changed slots.
+    pub(crate) fn resolve(
+        &self,
+        doll_slots: &[u32],
+        slot_map: HashMap<u32, u32>,
+        changes: &[(u32, Option<u32>)],
+    ) -> Result<Resolution> {
+        let baseline = self
+            .breaks(&slot_map)
+            .iter()
+            .map(Break::key)
+            .collect::<HashSet<(usize, Vec<u32>)>>();
+
+        let original = slot_map.clone();
+
+        let mut slot_map = slot_map;
+
+        let mut locked = HashSet::new();
+
+        for (slot_id, fragment_id) in changes {
+            match fragment_id {
+                Some(fragment_id) => slot_map.insert(*slot_id, *fragment_id),
+                None => slot_map.remove(slot_id),
+            };
+
+            locked.insert(*slot_id);
+        }
+
+        let mut displaced = vec![];
+
+        while let Some(violation) = self.find_violation(&slot_map, &locked, &baseline) {
+            match violation {
+                Violation::Displace(slot_id, reason) => {
+                    if self.policy == ConstraintPolicy::Reject {
+                        bail!(reason)
+                    }
+
+                    if locked.contains(&slot_id) {
+                        bail!("{} Slot {} can not be changed.", reason, slot_id)
+                    }
+
+                    if self
+                        .factory
+                        .get_slot(slot_id)
+                        .is_some_and(|slot| slot.required)
+                    {
+                        bail!("{} Slot {} cannot be empty.", reason, slot_id)
+                    }
+
+                    slot_map.remove(&slot_id);
+
+                    displaced.push(slot_id);
+                }
+                Violation::Require(fragment_id, fragments) => {
+                    if self.policy == ConstraintPolicy::Reject {
+                        bail!(
+                            "Fragment {} requires one of fragments {:?}.",
+                            fragment_id,
+                            fragments
+                        )
+                    }
+
+                    let candidate = fragments.iter().find_map(|required_id| {
+                        doll_slots
+                            .iter()
+                            .filter(|slot_id| !locked.contains(*slot_id))
+                            .find(|slot_id| {
+                                self.factory
+                                    .get_slot(**slot_id)
+                                    .is_some_and(|slot| slot.candidates.contains(required_id))
+                            })
+                            .map(|slot_id| (*slot_id, *required_id))
+                    });
+
+                    let Some((slot_id, required_id)) = candidate else {
+                        bail!(
+                            "Fragment {} requires one of fragments {:?}, but none can be used.",
+                            fragment_id,
+                            fragments
+                        )
+                    };
+
+                    slot_map.insert(slot_id, required_id);
+
+                    // The slot is refilled, but its own fragment is still gone if it had another.
+                    displaced.retain(|id| *id != slot_id);
+
+                    if original.get(&slot_id).is_some_and(|id| *id != required_id) {
+                        displaced.push(slot_id);
+                    }
+
+                    locked.insert(slot_id);
+                }
+            }
+        }
+
+        Ok(Resolution {
+            slot_map,
+            displaced,
+        })
+    }
+
+    /// Gets the constraints broken by the slot map, each with the slots involved.
+    ///
+    /// Conflicts are reported once per pair of slots, in the order of the constraints.
+    pub(crate) fn breaks(&self, slot_map: &HashMap<u32, u32>) -> Vec<Break> {
+        let slots_of = |fragment_id: u32| {
+            let mut slots = slot_map
+                .iter()
+                .filter(|(_, id)| **id == fragment_id)
+                .map(|(slot_id, _)| *slot_id)
+                .collect::<Vec<u32>>();
+
+            slots.sort();
+
+            slots
+        };
+
+        let mut breaks = vec![];
+
+        for (index, constraint) in self.constraints.iter().enumerate() {
+            let conflict = |a: u32, b: u32, reason: String| Break {
+                constraint: index,
+                kind: BreakKind::Conflict(a, b, reason),
+            };
+
+            match constraint {
+                Constraint::ExclusiveSlots(slots) => {
+                    let used = slots
+                        .iter()
+                        .filter(|slot_id| slot_map.contains_key(*slot_id))
+                        .copied()
+                        .collect::<Vec<u32>>();
+
+                    for (i, a) in used.iter().enumerate() {
+                        for b in &used[i + 1..] {
+                            breaks.push(conflict(
+                                *a,
+                                *b,
+                                format!("Slots {} and {} are exclusive.", a, b),
+                            ));
+                        }
+                    }
+                }
+                Constraint::ConflictsWithSlots { fragment, slots } => {
+                    for fragment_slot in slots_of(*fragment) {
+                        for other in slots.iter().filter(|slot_id| {
+                            **slot_id != fragment_slot && slot_map.contains_key(*slot_id)
+                        }) {
+                            breaks.push(conflict(
+                                fragment_slot,
+                                *other,
+                                format!("Fragment {} conflicts with slot {}.", fragment, other),
+                            ));
+                        }
+                    }
+                }
+                Constraint::ConflictsWithFragments {
+                    fragment,
+                    fragments,
+                } => {
+                    for fragment_slot in slots_of(*fragment) {
+                        for other in fragments {
+                            for other_slot in slots_of(*other) {
+                                if other_slot == fragment_slot {
+                                    continue;
+                                }
+
+                                breaks.push(conflict(
+                                    fragment_slot,
+                                    other_slot,
+                                    format!(
+                                        "Fragment {} conflicts with fragment {}.",
+                                        fragment, other
+                                    ),
+                                ));
+                            }
+                        }
+                    }
+                }
+                Constraint::Requires {
+                    fragment,
+                    fragments,
+                } => {
+                    if fragments.iter().any(|id| !slots_of(*id).is_empty()) {
+                        continue;
+                    }
+
+                    for fragment_slot in slots_of(*fragment) {
+                        breaks.push(Break {
+                            constraint: index,
+                            kind: BreakKind::Missing(fragment_slot, *fragment, fragments.clone()),
+                        });
+                    }
+                }
+            }
+        }
+
+        breaks
+    }
+
+    /// Finds the first broken constraint involving the locked slots or not in the baseline.
+    fn find_violation(
+        &self,
+        slot_map: &HashMap<u32, u32>,
+        locked: &HashSet<u32>,
+        baseline: &HashSet<(usize, Vec<u32>)>,
+    ) -> Option<Violation> {
+        self.breaks(slot_map).into_iter().find_map(|broken| {
+            let is_locked = broken
+                .slots()
+                .iter()
+                .any(|slot_id| locked.contains(slot_id));
+
+            if !is_locked && baseline.contains(&broken.key()) {
+                return None;
+            }
+
+            match broken.kind {
+                // Prefers displacing the slot which was not changed explicitly.
+                BreakKind::Conflict(a, b, reason) => Some(Violation::Displace(
+                    if locked.contains(&b) { a } else { b },
+                    reason,
+                )),
+                BreakKind::Missing(slot_id, fragment_id, fragments) => {
+                    if locked.contains(&slot_id) {
+                        Some(Violation::Require(fragment_id, fragments))
+                    } else {
+                        Some(Violation::Displace(
+                            slot_id,
+                            format!(
+                                "Fragment {} requires one of fragments {:?}.",
+                                fragment_id, fragments
+                            ),
+                        ))
+                    }
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Fixture {
+        factory: PaperdollFactory,
+        slots: Vec<u32>,
+        fragments: Vec<u32>,
+    }
+
+    /// Creates three optional slots, each with its own fragment as the only candidate.
+    fn fixture() -> Result<Fixture> {
+        let mut factory = PaperdollFactory::default();
+
+        let mut slots = vec![];
+        let mut fragments = vec![];
+
+        for _ in 0..3 {
+            let fragment_id = factory.add_fragment()?;
+            let slot_id = factory.add_slot()?;
+
+            if let Some(slot) = factory.get_slot_mut(slot_id) {
+                slot.candidates.push(fragment_id);
+            }
+
+            slots.push(slot_id);
+            fragments.push(fragment_id);
+        }
+
+        Ok(Fixture {
+            factory,
+            slots,
+            fragments,
+        })
+    }
+
+    fn resolve(
+        fixture: &Fixture,
+        constraints: &[Constraint],
+        policy: ConstraintPolicy,
+        slot_map: &[(u32, u32)],
+        changes: &[(u32, Option<u32>)],
+    ) -> Result<Resolution> {
+        let resolver = Resolver {
+            factory: &fixture.factory,
+            constraints,
+            policy,
+        };
+
+        resolver.resolve(&fixture.slots, slot_map.iter().copied().collect(), changes)
+    }
+
+    #[test]
+    fn reject_new_violation() {
+        let f = fixture().unwrap();
+        let (s, g) = (&f.slots, &f.fragments);
+
+        let constraints = [Constraint::ExclusiveSlots(vec![s[0], s[1]])];
+
+        let result = resolve(
+            &f,
+            &constraints,
+            ConstraintPolicy::Reject,
+            &[(s[0], g[0])],
+            &[(s[1], Some(g[1]))],
+        );
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn resolve_displaces_unchanged_slot() {
+        let f = fixture().unwrap();
+        let (s, g) = (&f.slots, &f.fragments);
+
+        let constraints = [Constraint::ExclusiveSlots(vec![s[0], s[1]])];
+
+        let resolution = resolve(
+            &f,
+            &constraints,
+            ConstraintPolicy::Resolve,
+            &[(s[0], g[0])],
+            &[(s[1], Some(g[1]))],
+        )
+        .unwrap();
+
+        assert_eq!(resolution.displaced, vec![s[0]]);
+        assert_eq!(resolution.slot_map.get(&s[1]), Some(&g[1]));
+        assert!(!resolution.slot_map.contains_key(&s[0]));
+    }
+
+    #[test]
+    fn existing_violation_ignored_for_unrelated_change() {
+        let f = fixture().unwrap();
+        let (s, g) = (&f.slots, &f.fragments);
+
+        let constraints = [Constraint::ExclusiveSlots(vec![s[0], s[1]])];
+
+        for policy in [ConstraintPolicy::Reject, ConstraintPolicy::Resolve] {
+            let resolution = resolve(
+                &f,
+                &constraints,
+                policy,
+                &[(s[0], g[0]), (s[1], g[1])],
+                &[(s[2], Some(g[2]))],
+            )
+            .unwrap();
+
+            assert!(resolution.displaced.is_empty());
+            assert_eq!(resolution.slot_map.len(), 3);
+        }
+    }
+
+    #[test]
+    fn existing_violation_reported_for_involved_change() {
+        let f = fixture().unwrap();
+        let (s, g) = (&f.slots, &f.fragments);
+
+        let constraints = [Constraint::ExclusiveSlots(vec![s[0], s[1]])];
+
+        let result = resolve(
+            &f,
+            &constraints,
+            ConstraintPolicy::Reject,
+            &[(s[0], g[0]), (s[1], g[1])],
+            &[(s[1], Some(g[1]))],
+        );
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn resolve_uses_required_fragment() {
+        let f = fixture().unwrap();
+        let (s, g) = (&f.slots, &f.fragments);
+
+        let constraints = [Constraint::Requires {
+            fragment: g[0],
+            fragments: vec![g[2]],
+        }];
+
+        let resolution = resolve(
+            &f,
+            &constraints,
+            ConstraintPolicy::Resolve,
+            &[],
+            &[(s[0], Some(g[0]))],
+        )
+        .unwrap();
+
+        assert_eq!(resolution.slot_map.get(&s[2]), Some(&g[2]));
+    }
+
+    #[test]
+    fn removing_required_fragment_displaces_dependent() {
+        let f = fixture().unwrap();
+        let (s, g) = (&f.slots, &f.fragments);
+
+        let constraints = [Constraint::Requires {
+            fragment: g[0],
+            fragments: vec![g[2]],
+        }];
+
+        let resolution = resolve(
+            &f,
+            &constraints,
+            ConstraintPolicy::Resolve,
+            &[(s[0], g[0]), (s[2], g[2])],
+            &[(s[2], None)],
+        )
+        .unwrap();
+
+        assert_eq!(resolution.displaced, vec![s[0]]);
+        assert!(resolution.slot_map.is_empty());
+    }
+
+    #[test]
+    fn required_fragment_replacing_another_displaces_slot() {
+        let mut f = fixture().unwrap();
+        let (s, g) = (f.slots.clone(), f.fragments.clone());
+
+        // The last slot can also hold the fragment of the second one.
+        if let Some(slot) = f.factory.get_slot_mut(s[2]) {
+            slot.candidates.push(g[1]);
+        }
+
+        let constraints = [Constraint::Requires {
+            fragment: g[0],
+            fragments: vec![g[1]],
+        }];
+
+        let resolution = resolve(
+            &f,
+            &constraints,
+            ConstraintPolicy::Resolve,
+            &[(s[2], g[2])],
+            &[(s[0], Some(g[0])), (s[1], None)],
+        )
+        .unwrap();
+
+        assert_eq!(resolution.slot_map.get(&s[2]), Some(&g[1]));
+        assert_eq!(resolution.displaced, vec![s[2]]);
+    }
+}
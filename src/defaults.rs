@@ -0,0 +1,25 @@
+use std::collections::BTreeMap;
+
+use bevy::{prelude::*, reflect::TypePath};
+use serde::{Deserialize, Serialize};
+
+/// Default fragments of slots for each doll, loaded from a sidecar file (.defaults.ron).
+///
+/// ```ron
+/// (
+///     dolls: {
+///         // doll id
+///         0: {
+///             // slot id: fragment id
+///             0: 1,
+///             2: 4,
+///         },
+///     },
+/// )
+/// ```
+///
+/// Apply them with [`crate::PaperdollAsset::set_default_fragments`].
+#[derive(Asset, Clone, Debug, Default, Deserialize, PartialEq, Serialize, TypePath)]
+pub struct PaperdollDefaults {
+    pub dolls: BTreeMap<u32, BTreeMap<u32, u32>>,
+}
@@ -0,0 +1,253 @@
+use std::{
+    io::Write,
+    path::{Component, Path},
+};
+
+use anyhow::{anyhow, bail, Result};
+use bevy::platform::collections::{HashMap, HashSet};
+use image::{ImageFormat, RgbaImage};
+use paperdoll_tar::paperdoll::{ImageData, Manifest, PaperdollFactory};
+
+use crate::loader::{self, DirManifest};
+
+/// A paperdoll source ready to be written, with the images encoded as PNG and keyed by their paths.
+pub(crate) struct Export {
+    pub(crate) manifest: Manifest,
+    /// The fragments with a mask image.
+    pub(crate) masks: Vec<u32>,
+    pub(crate) files: Vec<(String, Vec<u8>)>,
+}
+
+impl Export {
+    /// Encodes the images of the factory along with the fragment masks.
+    ///
+    /// Images without paths are named after their ids, eg. `fragment_1.png`, without clashing with
+    /// the other paths. Empty images are not written and their paths are cleared.
+    pub(crate) fn new(
+        factory: &PaperdollFactory,
+        fragment_masks: &HashMap<u32, ImageData>,
+    ) -> Result<Self> {
+        let mut manifest = factory.to_manifest();
+
+        let mut paths = manifest
+            .dolls
+            .iter()
+            .map(|doll| doll.path.clone())
+            .chain(
+                manifest
+                    .fragments
+                    .iter()
+                    .map(|fragment| fragment.path.clone()),
+            )
+            .filter(|path| !path.is_empty())
+            .collect::<HashSet<String>>();
+
+        let mut files = vec![];
+
+        let mut masks = vec![];
+
+        for doll in &mut manifest.dolls {
+            let Some(image) = factory.get_doll(doll.id()).map(|doll| &doll.image) else {
+                continue;
+            };
+
+            // Nothing is written, so the loader must not look for the image.
+            if is_empty(image) {
+                doll.path.clear();
+
+                continue;
+            }
+
+            if doll.path.is_empty() {
+                doll.path = unused_path(&mut paths, "doll", doll.id());
+            }
+
+            files.push((doll.path.clone(), encode_png(image)?));
+        }
+
+        for fragment in &mut manifest.fragments {
+            let Some(image) = factory
+                .get_fragment(fragment.id())
+                .map(|fragment| &fragment.image)
+            else {
+                continue;
+            };
+
+            if is_empty(image) {
+                fragment.path.clear();
+
+                continue;
+            }
+
+            if fragment.path.is_empty() {
+                fragment.path = unused_path(&mut paths, "fragment", fragment.id());
+            }
+
+            files.push((fragment.path.clone(), encode_png(image)?));
+
+            if let Some(mask) = fragment_masks.get(&fragment.id()) {
+                files.push((loader::mask_path(&fragment.path), encode_png(mask)?));
+
+                masks.push(fragment.id());
+            }
+        }
+
+        Ok(Self {
+            manifest,
+            masks,
+            files,
+        })
+    }
+
+    /// Writes the manifest and the images as a paperdoll archive (.ppd).
+    pub(crate) fn write_ppd<W: Write>(&self, writer: W) -> Result<()> {
+        let manifest = serde_yaml::to_string(&self.manifest)?;
+
+        let mut builder = tar::Builder::new(writer);
+
+        append(&mut builder, loader::MANIFEST_NAME, manifest.as_bytes())?;
+
+        for (path, bytes) in &self.files {
+            append(&mut builder, path, bytes)?;
+        }
+
+        builder.into_inner()?.flush()?;
+
+        Ok(())
+    }
+
+    /// Writes the manifest as `<name>.paperdoll.ron` and the images next to it.
+    ///
+    /// Fails before writing anything if an image path would leave the directory.
+    pub(crate) fn write_manifest_dir(&self, dir: &Path, name: &str) -> Result<()> {
+        for (path, _) in &self.files {
+            if !is_relative(path) {
+                bail!("Image path '{}' is not relative to the directory.", path)
+            }
+        }
+
+        std::fs::create_dir_all(dir)?;
+
+        let manifest = DirManifest::new(&self.manifest, self.masks.clone());
+
+        let manifest = ron::ser::to_string_pretty(&manifest, Default::default())?;
+
+        std::fs::write(dir.join(format!("{}.paperdoll.ron", name)), manifest)?;
+
+        for (path, bytes) in &self.files {
+            let path = dir.join(path);
+
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+
+            std::fs::write(path, bytes)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn append<W: Write>(builder: &mut tar::Builder<W>, path: &str, bytes: &[u8]) -> Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(bytes.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+
+    builder.append_data(&mut header, path, bytes)?;
+
+    Ok(())
+}
+
+fn encode_png(image: &ImageData) -> Result<Vec<u8>> {
+    let image = RgbaImage::from_raw(image.width, image.height, image.pixels.clone())
+        .ok_or(anyhow!("Image data does not match its size."))?;
+
+    let mut bytes = std::io::Cursor::new(Vec::new());
+
+    image.write_to(&mut bytes, ImageFormat::Png)?;
+
+    Ok(bytes.into_inner())
+}
+
+/// Names an image after its kind and id, eg. `fragment_1.png`, adding a suffix if the path is
+/// already used.
+fn unused_path(paths: &mut HashSet<String>, kind: &str, id: u32) -> String {
+    let mut path = format!("{}_{}.png", kind, id);
+
+    let mut suffix = 1;
+
+    while paths.contains(&path) {
+        path = format!("{}_{}_{}.png", kind, id, suffix);
+
+        suffix += 1;
+    }
+
+    paths.insert(path.clone());
+
+    path
+}
+
+/// Checks the path stays within the directory it is joined to.
+fn is_relative(path: &str) -> bool {
+    Path::new(path)
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+}
+
+fn is_empty(image: &ImageData) -> bool {
+    image.width == 0 || image.height == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use paperdoll_tar::paperdoll::ColorType;
+
+    use super::*;
+
+    #[test]
+    fn generated_paths_do_not_clash() {
+        let mut factory = PaperdollFactory::default();
+
+        let first = factory.add_fragment().unwrap();
+        let second = factory.add_fragment().unwrap();
+        let empty = factory.add_fragment().unwrap();
+
+        for id in [first, second] {
+            if let Some(fragment) = factory.get_fragment_mut(id) {
+                fragment.image = ImageData {
+                    width: 1,
+                    height: 1,
+                    color_type: ColorType::Rgba,
+                    pixels: vec![255; 4],
+                };
+            }
+        }
+
+        // The second fragment takes the name generated for the first one.
+        if let Some(fragment) = factory.get_fragment_mut(second) {
+            fragment.path = format!("fragment_{}.png", first);
+        }
+
+        if let Some(fragment) = factory.get_fragment_mut(empty) {
+            fragment.path = "empty.png".to_owned();
+        }
+
+        let export = Export::new(&factory, &HashMap::new()).unwrap();
+
+        let path = |id: u32| {
+            export
+                .manifest
+                .fragments
+                .iter()
+                .find(|fragment| fragment.id() == id)
+                .map(|fragment| fragment.path.clone())
+                .unwrap()
+        };
+
+        assert_eq!(path(first), format!("fragment_{}_1.png", first));
+        assert_eq!(path(second), format!("fragment_{}.png", first));
+        assert_eq!(path(empty), "");
+        assert_eq!(export.files.len(), 2);
+    }
+}
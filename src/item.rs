@@ -0,0 +1,26 @@
+use bevy::platform::collections::HashMap;
+
+/// A logical item drawn with a different fragment on each doll, eg. the same leather vest for
+/// several body types.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Item {
+    pub name: String,
+    /// The fragment used for each doll, keyed by doll id.
+    pub fragments: HashMap<u32, u32>,
+}
+
+impl Item {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            fragments: HashMap::new(),
+        }
+    }
+
+    /// Sets the fragment used for the given doll.
+    pub fn with_fragment(mut self, doll_id: u32, fragment_id: u32) -> Self {
+        self.fragments.insert(doll_id, fragment_id);
+
+        self
+    }
+}
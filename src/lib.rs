@@ -69,8 +69,39 @@
 //! See [examples](https://github.com/fralonra/bevy-paperdoll/blob/master/examples/README.md) for more.
 
 mod asset;
+mod asset_builder;
+mod blend;
+mod builder;
+mod clip;
+mod constraint;
+mod defaults;
+mod export;
+mod item;
+mod limits;
+mod lint;
 mod loader;
+mod mask;
+mod merge;
+mod outfit;
+mod palette;
 mod plugin;
+mod render;
+mod variant;
 
 pub use asset::{PaperdollAsset, PaperdollId};
+pub use asset_builder::{PaperdollAssetBuilder, SlotOptions};
+pub use blend::{BlendMode, SlotBlend};
+pub use builder::{PaperdollBuilder, SlotFill};
+pub use clip::{ClipMask, ClipRegion, ClipTarget};
+pub use constraint::{Constraint, ConstraintPolicy};
+pub use defaults::PaperdollDefaults;
+pub use item::Item;
+pub use limits::{Limit, LoadLimits};
+pub use lint::{Diagnostic, Lint, Severity, ValidationLevel};
+pub use loader::{Compression, ImageOwner, PaperdollLoaderError, PaperdollLoaderSettings};
+pub use mask::MaskColors;
+pub use merge::{MergeConflict, MergeReport};
+pub use outfit::OutfitProblem;
+pub use palette::{ColorSwap, PaletteSwap};
 pub use plugin::PaperdollPlugin;
+pub use variant::{FragmentVariant, VariantCondition};
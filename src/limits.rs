@@ -0,0 +1,66 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// A resource checked by [`LoadLimits`].
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Limit {
+    FileSize,
+    ImageWidth,
+    ImageHeight,
+    Dolls,
+    Slots,
+    Fragments,
+    TotalPixels,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::FileSize => write!(f, "file size"),
+            Self::ImageWidth => write!(f, "image width"),
+            Self::ImageHeight => write!(f, "image height"),
+            Self::Dolls => write!(f, "number of dolls"),
+            Self::Slots => write!(f, "number of slots"),
+            Self::Fragments => write!(f, "number of fragments"),
+            Self::TotalPixels => write!(f, "number of decoded pixels"),
+        }
+    }
+}
+
+/// Limits applied when loading paperdoll assets, eg. packs imported by players.
+///
+/// Every limit is disabled by default.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(default)]
+pub struct LoadLimits {
+    /// The maximum size of the file in bytes.
+    pub max_file_size: Option<u64>,
+    /// The maximum width of each image and doll in pixels.
+    pub max_image_width: Option<u32>,
+    /// The maximum height of each image and doll in pixels.
+    pub max_image_height: Option<u32>,
+    pub max_dolls: Option<usize>,
+    pub max_slots: Option<usize>,
+    pub max_fragments: Option<usize>,
+    /// The maximum number of pixels decoded from all images, including masks and the size of
+    /// each doll.
+    pub max_total_pixels: Option<u64>,
+}
+
+impl LoadLimits {
+    /// Gets the maximum if the value exceeds the limit.
+    pub(crate) fn exceeded(&self, limit: Limit, value: u64) -> Option<u64> {
+        let max = match limit {
+            Limit::FileSize => self.max_file_size,
+            Limit::ImageWidth => self.max_image_width.map(u64::from),
+            Limit::ImageHeight => self.max_image_height.map(u64::from),
+            Limit::Dolls => self.max_dolls.map(|max| max as u64),
+            Limit::Slots => self.max_slots.map(|max| max as u64),
+            Limit::Fragments => self.max_fragments.map(|max| max as u64),
+            Limit::TotalPixels => self.max_total_pixels,
+        }?;
+
+        (value > max).then_some(max)
+    }
+}
@@ -0,0 +1,252 @@
+use std::fmt;
+
+use paperdoll_tar::paperdoll::PaperdollFactory;
+use serde::{Deserialize, Serialize};
+
+use crate::loader::ImageOwner;
+
+/// How serious a [`Diagnostic`] is.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Severity {
+    /// The asset can be used, but may render unexpectedly.
+    Warning,
+    /// The asset is broken, eg. some paperdolls can not be created.
+    Error,
+}
+
+/// A problem found in a paperdoll asset.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Lint {
+    /// The doll refers to a slot which does not exist.
+    UnknownDollSlot { doll: u32, slot: u32 },
+    /// The slot is required but has no candidates.
+    RequiredSlotWithoutCandidates { slot: u32 },
+    /// A candidate of the slot refers to a fragment which does not exist.
+    UnknownCandidate { slot: u32, fragment: u32 },
+    /// A candidate of the constrained slot is bigger than the slot, so it is cropped. Unconstrained
+    /// slots draw fragments at their own size and are never reported.
+    FragmentLargerThanSlot { slot: u32, fragment: u32 },
+    /// The image could not be decoded and was dropped, see
+    /// [`crate::PaperdollLoaderSettings::skip_corrupt_fragments`].
+    SkippedImage {
+        entry: String,
+        owner: ImageOwner,
+        reason: String,
+    },
+}
+
+impl Lint {
+    pub fn severity(&self) -> Severity {
+        match self {
+            Self::UnknownDollSlot { .. }
+            | Self::RequiredSlotWithoutCandidates { .. }
+            | Self::UnknownCandidate { .. } => Severity::Error,
+            Self::FragmentLargerThanSlot { .. } | Self::SkippedImage { .. } => Severity::Warning,
+        }
+    }
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownDollSlot { doll, slot } => {
+                write!(f, "Doll {} refers to unknown slot {}.", doll, slot)
+            }
+            Self::RequiredSlotWithoutCandidates { slot } => {
+                write!(f, "Slot {} is required but has no candidates.", slot)
+            }
+            Self::UnknownCandidate { slot, fragment } => {
+                write!(f, "Slot {} refers to unknown fragment {}.", slot, fragment)
+            }
+            Self::FragmentLargerThanSlot { slot, fragment } => {
+                write!(f, "Fragment {} is larger than slot {}.", fragment, slot)
+            }
+            Self::SkippedImage {
+                entry,
+                owner,
+                reason,
+            } => write!(f, "Image '{}' of {} was skipped: {}", entry, owner, reason),
+        }
+    }
+}
+
+/// A lint along with its severity.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Diagnostic {
+    pub lint: Lint,
+    pub severity: Severity,
+}
+
+impl From<Lint> for Diagnostic {
+    fn from(lint: Lint) -> Self {
+        Self {
+            severity: lint.severity(),
+            lint,
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.severity {
+            Severity::Warning => write!(f, "warning: {}", self.lint),
+            Severity::Error => write!(f, "error: {}", self.lint),
+        }
+    }
+}
+
+/// How the validation report of a paperdoll asset is handled when loading.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum ValidationLevel {
+    /// The asset is not validated.
+    Off,
+    /// Problems are logged and stored in the asset, see [`crate::PaperdollAsset::diagnostics`].
+    #[default]
+    Warn,
+    /// Loading fails if any error is found.
+    DenyErrors,
+    /// Loading fails if any warning or error is found.
+    DenyWarnings,
+}
+
+impl ValidationLevel {
+    pub(crate) fn denies(&self, severity: Severity) -> bool {
+        match self {
+            Self::Off | Self::Warn => false,
+            Self::DenyErrors => severity == Severity::Error,
+            Self::DenyWarnings => true,
+        }
+    }
+}
+
+/// Checks the dolls, slots and fragments of the factory for broken references and sizes.
+///
+/// Diagnostics are sorted by dolls first, then slots, each by id.
+pub(crate) fn lint(factory: &PaperdollFactory) -> Vec<Diagnostic> {
+    let mut lints = vec![];
+
+    for (doll_id, doll) in factory.dolls() {
+        for slot_id in &doll.slots {
+            if factory.get_slot(*slot_id).is_none() {
+                lints.push(Lint::UnknownDollSlot {
+                    doll: *doll_id,
+                    slot: *slot_id,
+                });
+            }
+        }
+    }
+
+    for (slot_id, slot) in factory.slots() {
+        if slot.required && slot.candidates.is_empty() {
+            lints.push(Lint::RequiredSlotWithoutCandidates { slot: *slot_id });
+        }
+
+        for fragment_id in &slot.candidates {
+            let Some(fragment) = factory.get_fragment(*fragment_id) else {
+                lints.push(Lint::UnknownCandidate {
+                    slot: *slot_id,
+                    fragment: *fragment_id,
+                });
+
+                continue;
+            };
+
+            // Unconstrained slots draw fragments at their own size.
+            if !slot.constrainted {
+                continue;
+            }
+
+            if fragment.image.width > slot.width || fragment.image.height > slot.height {
+                lints.push(Lint::FragmentLargerThanSlot {
+                    slot: *slot_id,
+                    fragment: *fragment_id,
+                });
+            }
+        }
+    }
+
+    lints.into_iter().map(Diagnostic::from).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use paperdoll_tar::paperdoll::{ColorType, ImageData};
+
+    use super::*;
+
+    /// Adds a slot of 2x2 pixels with a 4x4 fragment as its only candidate.
+    fn add_slot(factory: &mut PaperdollFactory, constrained: bool) -> Result<(u32, u32)> {
+        let fragment_id = factory.add_fragment()?;
+        let slot_id = factory.add_slot()?;
+
+        if let Some(fragment) = factory.get_fragment_mut(fragment_id) {
+            fragment.image = ImageData {
+                width: 4,
+                height: 4,
+                color_type: ColorType::Rgba,
+                pixels: vec![255; 64],
+            };
+        }
+
+        if let Some(slot) = factory.get_slot_mut(slot_id) {
+            slot.width = 2;
+            slot.height = 2;
+            slot.constrainted = constrained;
+            slot.candidates.push(fragment_id);
+        }
+
+        Ok((slot_id, fragment_id))
+    }
+
+    #[test]
+    fn larger_fragment_only_reported_in_constrained_slot() {
+        let mut factory = PaperdollFactory::default();
+
+        add_slot(&mut factory, false).unwrap();
+
+        let (slot_id, fragment_id) = add_slot(&mut factory, true).unwrap();
+
+        assert_eq!(
+            lint(&factory),
+            vec![Diagnostic {
+                lint: Lint::FragmentLargerThanSlot {
+                    slot: slot_id,
+                    fragment: fragment_id,
+                },
+                severity: Severity::Warning,
+            }]
+        );
+    }
+
+    #[test]
+    fn broken_references_are_errors() {
+        let mut factory = PaperdollFactory::default();
+
+        let slot_id = factory.add_slot().unwrap();
+
+        if let Some(slot) = factory.get_slot_mut(slot_id) {
+            slot.required = true;
+        }
+
+        if let Some(doll) = factory.get_doll_mut(0) {
+            doll.slots.push(99);
+        }
+
+        let lints = lint(&factory)
+            .into_iter()
+            .map(|diagnostic| (diagnostic.severity, diagnostic.lint))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            lints,
+            vec![
+                (Severity::Error, Lint::UnknownDollSlot { doll: 0, slot: 99 }),
+                (
+                    Severity::Error,
+                    Lint::RequiredSlotWithoutCandidates { slot: slot_id }
+                ),
+            ]
+        );
+    }
+}
@@ -1,18 +1,223 @@
+use std::{
+    fmt,
+    io::{Cursor, Read},
+    path::Path,
+};
+
 use bevy::{
     asset::{io::Reader, AssetLoader, LoadContext},
+    log::{error, warn},
+    platform::collections::HashMap,
     prelude::*,
+    tasks::futures_lite::{self, AsyncRead},
+};
+use image::{ImageError, ImageReader};
+use paperdoll_tar::paperdoll::{
+    ColorType, Doll, Fragment, ImageData, Manifest, Meta, PaperdollFactory, Slot, VERSION,
 };
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-use crate::PaperdollAsset;
+use crate::{
+    limits::{Limit, LoadLimits},
+    lint::{self, Diagnostic, Lint, Severity, ValidationLevel},
+    PaletteSwap, PaperdollAsset, PaperdollDefaults,
+};
+
+pub(crate) const MANIFEST_NAME: &str = "manifest.yml";
+
+/// The manifest read by [`PaperdollManifestLoader`], which is the manifest of .ppd files along
+/// with the fragments having a mask image.
+#[derive(Debug, Deserialize, Serialize)]
+pub(crate) struct DirManifest {
+    meta: Meta,
+    dolls: Vec<Doll>,
+    slots: Vec<Slot>,
+    fragments: Vec<Fragment>,
+    /// The fragments with a mask image next to their own, see [`mask_path`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    masks: Vec<u32>,
+}
+
+impl DirManifest {
+    pub(crate) fn new(manifest: &Manifest, masks: Vec<u32>) -> Self {
+        Self {
+            meta: manifest.meta.clone(),
+            dolls: manifest.dolls.clone(),
+            slots: manifest.slots.clone(),
+            fragments: manifest.fragments.clone(),
+            masks,
+        }
+    }
+
+    fn into_parts(self) -> (Manifest, Vec<u32>) {
+        let manifest = Manifest {
+            meta: self.meta,
+            dolls: self.dolls,
+            slots: self.slots,
+            fragments: self.fragments,
+        };
+
+        (manifest, self.masks)
+    }
+}
+
+/// A compression format of paperdoll files, detected from the first bytes of the file.
+///
+/// Compressed files can be loaded with the `gzip` and `zstd` features.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    fn detect(header: &[u8]) -> Option<Self> {
+        if header.starts_with(&[0x1f, 0x8b]) {
+            Some(Self::Gzip)
+        } else if header.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Self::Zstd)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Gzip => write!(f, "gzip"),
+            Self::Zstd => write!(f, "zstd"),
+        }
+    }
+}
+
+/// The owner of an image in a paperdoll asset.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ImageOwner {
+    Doll(u32),
+    Fragment(u32),
+    /// The mask image of the fragment, see [`crate::MaskColors`].
+    FragmentMask(u32),
+}
+
+impl fmt::Display for ImageOwner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Doll(id) => write!(f, "doll {}", id),
+            Self::Fragment(id) => write!(f, "fragment {}", id),
+            Self::FragmentMask(id) => write!(f, "mask of fragment {}", id),
+        }
+    }
+}
 
 #[non_exhaustive]
 #[derive(Debug, Error)]
 pub enum PaperdollLoaderError {
     #[error("Could not load paperdoll source: {0}")]
     Io(#[from] std::io::Error),
+    #[error("Could not read archive of '{asset}': {source}")]
+    Archive {
+        asset: String,
+        source: std::io::Error,
+    },
+    #[error("Could not read entry '{entry}' of '{asset}': {source}")]
+    Entry {
+        asset: String,
+        entry: String,
+        source: std::io::Error,
+    },
+    #[error("'{asset}' is compressed with {compression}, enable the '{compression}' feature.")]
+    UnsupportedCompression {
+        asset: String,
+        compression: Compression,
+    },
+    #[error("Manifest '{}' not found in '{asset}'.", MANIFEST_NAME)]
+    MissingManifest { asset: String },
+    #[error("Could not parse manifest of '{asset}': {source}")]
+    Manifest {
+        asset: String,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    #[error(
+        "Unsupported format version {version} of '{asset}', expected {}.",
+        VERSION
+    )]
+    UnsupportedVersion { asset: String, version: u32 },
+    #[error("Image '{entry}' of {owner} not found in '{asset}'.")]
+    MissingImage {
+        asset: String,
+        entry: String,
+        owner: ImageOwner,
+    },
+    #[error("Could not decode image '{entry}' of {owner} in '{asset}': {source}")]
+    Image {
+        asset: String,
+        entry: String,
+        owner: ImageOwner,
+        source: image::ImageError,
+    },
+    #[error("The {limit} of '{asset}' exceeds the limit: {value} > {max}.")]
+    LimitExceeded {
+        asset: String,
+        limit: Limit,
+        value: u64,
+        max: u64,
+    },
+    #[error(
+        "The {limit} of image '{entry}' of {owner} in '{asset}' exceeds the limit: {value} > {max}."
+    )]
+    ImageLimitExceeded {
+        asset: String,
+        entry: String,
+        owner: ImageOwner,
+        limit: Limit,
+        value: u64,
+        max: u64,
+    },
+    #[error("The {limit} of doll {doll} in '{asset}' exceeds the limit: {value} > {max}.")]
+    DollLimitExceeded {
+        asset: String,
+        doll: u32,
+        limit: Limit,
+        value: u64,
+        max: u64,
+    },
     #[error("Could not load paperdoll from source: {0}")]
     Load(#[from] anyhow::Error),
+    #[error("Paperdoll source failed validation: {}", format_diagnostics(.0))]
+    Invalid(Vec<Diagnostic>),
+}
+
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum PaletteSwapLoaderError {
+    #[error("Could not load palette source: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Could not parse palette from source: {0}")]
+    Parse(#[from] ron::error::SpannedError),
+}
+
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum PaperdollDefaultsLoaderError {
+    #[error("Could not load paperdoll defaults source: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Could not parse paperdoll defaults from source: {0}")]
+    Parse(#[from] ron::error::SpannedError),
+}
+
+/// Settings of [`PaperdollLoader`] and [`PaperdollManifestLoader`].
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PaperdollLoaderSettings {
+    /// Limits for loading untrusted files.
+    pub limits: LoadLimits,
+    /// Drops fragments whose images can not be decoded, instead of failing. Dropped fragments are
+    /// removed from the asset and the candidates of slots, and reported in the diagnostics.
+    pub skip_corrupt_fragments: bool,
+    /// How problems found in the asset are handled.
+    pub validation: ValidationLevel,
 }
 
 /// Bevy asset loader for loading paperdoll asset (.ppd).
@@ -22,10 +227,625 @@ pub struct PaperdollLoader;
 impl AssetLoader for PaperdollLoader {
     type Asset = PaperdollAsset;
 
-    type Settings = ();
+    type Settings = PaperdollLoaderSettings;
 
     type Error = PaperdollLoaderError;
 
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        settings: &Self::Settings,
+        load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let asset = load_context.path().to_string();
+
+        let bytes = read_ppd_bytes(reader, &asset, &settings.limits).await?;
+
+        read_ppd(&asset, &bytes, settings)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &[
+            paperdoll_tar::EXTENSION_NAME,
+            #[cfg(feature = "gzip")]
+            "ppd.gz",
+            #[cfg(feature = "zstd")]
+            "ppd.zst",
+        ]
+    }
+}
+
+/// Bevy asset loader for loading paperdoll asset from a manifest (.paperdoll.ron or
+/// .paperdoll.json) and loose images.
+///
+/// The manifest has the same structure as `manifest.yml` in .ppd files, with an optional `masks`
+/// list of the fragments having a mask image, see [`crate::MaskColors`]. Image paths are relative
+/// to the manifest, and the asset is reloaded when any of the images changes.
+#[derive(Default, TypePath)]
+pub struct PaperdollManifestLoader;
+
+impl AssetLoader for PaperdollManifestLoader {
+    type Asset = PaperdollAsset;
+
+    type Settings = PaperdollLoaderSettings;
+
+    type Error = PaperdollLoaderError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        settings: &Self::Settings,
+        load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let asset = load_context.path().to_string();
+
+        let bytes = read_bytes(reader, &asset, &settings.limits).await?;
+
+        let is_json = load_context
+            .path()
+            .path()
+            .extension()
+            .is_some_and(|extension| extension == "json");
+
+        let manifest: Result<DirManifest, Box<dyn std::error::Error + Send + Sync>> = if is_json {
+            serde_json::from_slice(&bytes).map_err(Into::into)
+        } else {
+            ron::de::from_bytes(&bytes).map_err(Into::into)
+        };
+
+        let manifest = manifest.map_err(|source| PaperdollLoaderError::Manifest {
+            asset: asset.clone(),
+            source,
+        })?;
+
+        let (manifest, masks) = manifest.into_parts();
+
+        let mut source = Source::new(&asset, HashMap::new(), &settings.limits);
+
+        let factory = source.read_factory(manifest)?;
+
+        for path in image_paths(&factory, &masks) {
+            let Ok(asset_path) = load_context.path().resolve_embed(&path) else {
+                continue;
+            };
+
+            // Images not found are reported when decoding.
+            let Ok(bytes) = load_context.read_asset_bytes(asset_path).await else {
+                continue;
+            };
+
+            source.check_limit(Limit::FileSize, bytes.len() as u64)?;
+
+            source.entries.insert(path, bytes);
+        }
+
+        source.into_asset(factory, settings)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["paperdoll.ron", "paperdoll.json"]
+    }
+}
+
+fn format_diagnostics(diagnostics: &[Diagnostic]) -> String {
+    diagnostics
+        .iter()
+        .map(|diagnostic| diagnostic.to_string())
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// Gets the paths of the images of dolls and fragments, including the masks of the given
+/// fragments.
+fn image_paths(factory: &PaperdollFactory, masks: &[u32]) -> Vec<String> {
+    let mut paths = factory
+        .dolls()
+        .map(|(_, doll)| doll.path.clone())
+        .chain(factory.fragments().flat_map(|(id, fragment)| {
+            let mask_path = masks.contains(id).then(|| mask_path(&fragment.path));
+
+            [Some(fragment.path.clone()), mask_path]
+                .into_iter()
+                .flatten()
+        }))
+        .filter(|path| !path.is_empty())
+        .collect::<Vec<String>>();
+
+    paths.sort();
+    paths.dedup();
+
+    paths
+}
+
+/// Gets the path of the mask image of a fragment, stored next to the fragment image as
+/// `<name>.mask.png`.
+pub(crate) fn mask_path(path: &str) -> String {
+    if path.is_empty() {
+        return String::new();
+    }
+
+    Path::new(path)
+        .with_extension("mask.png")
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Reads a paperdoll archive (.ppd).
+pub(crate) fn read_ppd(
+    asset: &str,
+    bytes: &[u8],
+    settings: &PaperdollLoaderSettings,
+) -> Result<PaperdollAsset, PaperdollLoaderError> {
+    if let Some(max) = settings
+        .limits
+        .exceeded(Limit::FileSize, bytes.len() as u64)
+    {
+        return Err(PaperdollLoaderError::LimitExceeded {
+            asset: asset.to_owned(),
+            limit: Limit::FileSize,
+            value: bytes.len() as u64,
+            max,
+        });
+    }
+
+    let mut source = Source::read(asset, bytes, &settings.limits)?;
+
+    let manifest =
+        source
+            .entries
+            .get(MANIFEST_NAME)
+            .ok_or_else(|| PaperdollLoaderError::MissingManifest {
+                asset: asset.to_owned(),
+            })?;
+
+    let manifest = serde_yaml::from_slice::<Manifest>(manifest).map_err(|err| {
+        PaperdollLoaderError::Manifest {
+            asset: asset.to_owned(),
+            source: err.into(),
+        }
+    })?;
+
+    let factory = source.read_factory(manifest)?;
+
+    source.into_asset(factory, settings)
+}
+
+/// Reads the whole source, failing if it exceeds the size limit.
+///
+/// The limit applies to the decompressed size of compressed sources.
+async fn read_bytes<R: AsyncRead + Unpin>(
+    mut reader: R,
+    asset: &str,
+    limits: &LoadLimits,
+) -> Result<Vec<u8>, PaperdollLoaderError> {
+    let mut bytes = Vec::new();
+
+    let Some(max) = limits.max_file_size else {
+        futures_lite::AsyncReadExt::read_to_end(&mut reader, &mut bytes).await?;
+
+        return Ok(bytes);
+    };
+
+    let mut limited = futures_lite::AsyncReadExt::take(reader, max.saturating_add(1));
+
+    futures_lite::AsyncReadExt::read_to_end(&mut limited, &mut bytes).await?;
+
+    if bytes.len() as u64 > max {
+        return Err(PaperdollLoaderError::LimitExceeded {
+            asset: asset.to_owned(),
+            limit: Limit::FileSize,
+            value: bytes.len() as u64,
+            max,
+        });
+    }
+
+    Ok(bytes)
+}
+
+/// Reads a paperdoll archive, decompressing it if it is compressed.
+async fn read_ppd_bytes(
+    reader: &mut dyn Reader,
+    asset: &str,
+    limits: &LoadLimits,
+) -> Result<Vec<u8>, PaperdollLoaderError> {
+    // Reads may return fewer bytes than asked, so keep reading until the magic bytes are known.
+    let mut header = [0; 4];
+    let mut len = 0;
+
+    while len < header.len() {
+        let read = futures_lite::AsyncReadExt::read(reader, &mut header[len..]).await?;
+
+        if read == 0 {
+            break;
+        }
+
+        len += read;
+    }
+
+    let header = &header[..len];
+
+    let reader =
+        futures_lite::io::BufReader::new(futures_lite::AsyncReadExt::chain(header, reader));
+
+    match Compression::detect(header) {
+        None => read_bytes(reader, asset, limits).await,
+        #[cfg(feature = "gzip")]
+        Some(Compression::Gzip) => {
+            let decoder = async_compression::futures::bufread::GzipDecoder::new(reader);
+
+            read_bytes(decoder, asset, limits).await
+        }
+        #[cfg(feature = "zstd")]
+        Some(Compression::Zstd) => {
+            let decoder = async_compression::futures::bufread::ZstdDecoder::new(reader);
+
+            read_bytes(decoder, asset, limits).await
+        }
+        #[cfg(not(all(feature = "gzip", feature = "zstd")))]
+        Some(compression) => Err(PaperdollLoaderError::UnsupportedCompression {
+            asset: asset.to_owned(),
+            compression,
+        }),
+    }
+}
+
+/// The files of a paperdoll source being loaded, keyed by their paths.
+struct Source<'a> {
+    asset: &'a str,
+    entries: HashMap<String, Vec<u8>>,
+    limits: &'a LoadLimits,
+    /// The number of pixels decoded so far.
+    pixels: u64,
+}
+
+impl<'a> Source<'a> {
+    /// Reads all files of the archive, keyed by their paths.
+    fn read(
+        asset: &'a str,
+        bytes: &[u8],
+        limits: &'a LoadLimits,
+    ) -> Result<Self, PaperdollLoaderError> {
+        let archive_error = |source| PaperdollLoaderError::Archive {
+            asset: asset.to_owned(),
+            source,
+        };
+
+        let mut entries = HashMap::new();
+
+        let mut archive = tar::Archive::new(bytes);
+
+        for entry in archive.entries().map_err(archive_error)? {
+            let mut entry = entry.map_err(archive_error)?;
+
+            let path = entry
+                .path()
+                .map_err(archive_error)?
+                .to_string_lossy()
+                .into_owned();
+
+            let mut buf = Vec::new();
+
+            if let Err(source) = entry.read_to_end(&mut buf) {
+                return Err(PaperdollLoaderError::Entry {
+                    asset: asset.to_owned(),
+                    entry: path,
+                    source,
+                });
+            }
+
+            entries.insert(path, buf);
+        }
+
+        Ok(Self::new(asset, entries, limits))
+    }
+
+    fn new(asset: &'a str, entries: HashMap<String, Vec<u8>>, limits: &'a LoadLimits) -> Self {
+        Self {
+            asset,
+            entries,
+            limits,
+            pixels: 0,
+        }
+    }
+
+    fn check_limit(&self, limit: Limit, value: u64) -> Result<(), PaperdollLoaderError> {
+        match self.limits.exceeded(limit, value) {
+            Some(max) => Err(PaperdollLoaderError::LimitExceeded {
+                asset: self.asset.to_owned(),
+                limit,
+                value,
+                max,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    fn decode_image(
+        &mut self,
+        entry: &str,
+        owner: ImageOwner,
+    ) -> Result<ImageData, PaperdollLoaderError> {
+        let image_error = |source| PaperdollLoaderError::Image {
+            asset: self.asset.to_owned(),
+            entry: entry.to_owned(),
+            owner,
+            source,
+        };
+
+        let bytes = self
+            .entries
+            .get(entry)
+            .ok_or_else(|| PaperdollLoaderError::MissingImage {
+                asset: self.asset.to_owned(),
+                entry: entry.to_owned(),
+                owner,
+            })?;
+
+        let (width, height) = ImageReader::new(Cursor::new(bytes))
+            .with_guessed_format()
+            .map_err(|err| image_error(ImageError::IoError(err)))?
+            .into_dimensions()
+            .map_err(image_error)?;
+
+        let pixels = self.pixels + width as u64 * height as u64;
+
+        for (limit, value) in [
+            (Limit::ImageWidth, width as u64),
+            (Limit::ImageHeight, height as u64),
+            (Limit::TotalPixels, pixels),
+        ] {
+            if let Some(max) = self.limits.exceeded(limit, value) {
+                return Err(PaperdollLoaderError::ImageLimitExceeded {
+                    asset: self.asset.to_owned(),
+                    entry: entry.to_owned(),
+                    owner,
+                    limit,
+                    value,
+                    max,
+                });
+            }
+        }
+
+        let image = image::load_from_memory(bytes)
+            .map_err(image_error)?
+            .into_rgba8();
+
+        self.pixels = pixels;
+
+        Ok(ImageData {
+            width: image.width(),
+            height: image.height(),
+            color_type: ColorType::Rgba,
+            pixels: image.into_raw(),
+        })
+    }
+
+    /// Creates the factory from the manifest, without images.
+    ///
+    /// The size of each doll counts towards the decoded pixels, as it is rendered.
+    fn read_factory(
+        &mut self,
+        manifest: Manifest,
+    ) -> Result<PaperdollFactory, PaperdollLoaderError> {
+        if manifest.meta.version != VERSION {
+            return Err(PaperdollLoaderError::UnsupportedVersion {
+                asset: self.asset.to_owned(),
+                version: manifest.meta.version,
+            });
+        }
+
+        let factory = PaperdollFactory::from_manifest(manifest)?;
+
+        self.check_limit(Limit::Dolls, factory.dolls().count() as u64)?;
+        self.check_limit(Limit::Slots, factory.slots().count() as u64)?;
+        self.check_limit(Limit::Fragments, factory.fragments().count() as u64)?;
+
+        let mut dolls = factory.dolls().collect::<Vec<_>>();
+
+        dolls.sort_by_key(|(id, _)| **id);
+
+        for (doll_id, doll) in dolls {
+            let pixels = self.pixels + doll.width as u64 * doll.height as u64;
+
+            for (limit, value) in [
+                (Limit::ImageWidth, doll.width as u64),
+                (Limit::ImageHeight, doll.height as u64),
+                (Limit::TotalPixels, pixels),
+            ] {
+                if let Some(max) = self.limits.exceeded(limit, value) {
+                    return Err(PaperdollLoaderError::DollLimitExceeded {
+                        asset: self.asset.to_owned(),
+                        doll: *doll_id,
+                        limit,
+                        value,
+                        max,
+                    });
+                }
+            }
+
+            self.pixels = pixels;
+        }
+
+        Ok(factory)
+    }
+
+    /// Decodes the image, or reports it if it is corrupt and corrupt images are skipped.
+    fn decode_or_skip(
+        &mut self,
+        entry: &str,
+        owner: ImageOwner,
+        skip_corrupt: bool,
+    ) -> Result<Result<ImageData, Lint>, PaperdollLoaderError> {
+        let reason = match self.decode_image(entry, owner) {
+            Ok(image) => return Ok(Ok(image)),
+            Err(PaperdollLoaderError::Image { source, .. }) if skip_corrupt => source.to_string(),
+            Err(PaperdollLoaderError::MissingImage { .. }) if skip_corrupt => {
+                "Image not found.".to_owned()
+            }
+            Err(err) => return Err(err),
+        };
+
+        Ok(Err(Lint::SkippedImage {
+            entry: entry.to_owned(),
+            owner,
+            reason,
+        }))
+    }
+
+    /// Decodes the images and validates the factory.
+    fn into_asset(
+        mut self,
+        mut factory: PaperdollFactory,
+        settings: &PaperdollLoaderSettings,
+    ) -> Result<PaperdollAsset, PaperdollLoaderError> {
+        let mut lints = self.read_images(&mut factory, settings.skip_corrupt_fragments)?;
+
+        let (fragment_masks, mask_lints) =
+            self.read_fragment_masks(&factory, settings.skip_corrupt_fragments)?;
+
+        lints.extend(mask_lints);
+
+        let mut diagnostics = lints
+            .into_iter()
+            .map(Diagnostic::from)
+            .collect::<Vec<Diagnostic>>();
+
+        if settings.validation != ValidationLevel::Off {
+            diagnostics.extend(lint::lint(&factory));
+        }
+
+        for diagnostic in &diagnostics {
+            match diagnostic.severity {
+                Severity::Warning => warn!("{}: {}", self.asset, diagnostic.lint),
+                Severity::Error => error!("{}: {}", self.asset, diagnostic.lint),
+            }
+        }
+
+        if diagnostics
+            .iter()
+            .any(|diagnostic| settings.validation.denies(diagnostic.severity))
+        {
+            return Err(PaperdollLoaderError::Invalid(diagnostics));
+        }
+
+        Ok(PaperdollAsset::new(factory)
+            .with_diagnostics(diagnostics)
+            .with_fragment_masks(fragment_masks))
+    }
+
+    /// Reads the mask images of fragments, see [`mask_path`].
+    ///
+    /// Returns the masks and the masks skipped.
+    fn read_fragment_masks(
+        &mut self,
+        factory: &PaperdollFactory,
+        skip_corrupt: bool,
+    ) -> Result<(HashMap<u32, ImageData>, Vec<Lint>), PaperdollLoaderError> {
+        let mut fragment_masks = HashMap::new();
+
+        let mut skipped = vec![];
+
+        for (id, fragment) in factory.fragments() {
+            if fragment.path.is_empty() {
+                continue;
+            }
+
+            let mask_path = mask_path(&fragment.path);
+
+            if !self.entries.contains_key(&mask_path) {
+                continue;
+            }
+
+            match self.decode_or_skip(&mask_path, ImageOwner::FragmentMask(*id), skip_corrupt)? {
+                Ok(mask) => {
+                    fragment_masks.insert(*id, mask);
+                }
+                Err(lint) => skipped.push(lint),
+            }
+        }
+
+        Ok((fragment_masks, skipped))
+    }
+
+    /// Decodes the images of dolls and fragments.
+    ///
+    /// Returns the fragments skipped, which are removed from the factory and the candidates of all
+    /// slots, so their masks are not decoded either.
+    fn read_images(
+        &mut self,
+        factory: &mut PaperdollFactory,
+        skip_corrupt: bool,
+    ) -> Result<Vec<Lint>, PaperdollLoaderError> {
+        let doll_paths = factory
+            .dolls()
+            .filter(|(_, doll)| !doll.path.is_empty())
+            .map(|(id, doll)| (*id, doll.path.clone()))
+            .collect::<Vec<(u32, String)>>();
+
+        for (id, path) in doll_paths {
+            let image = self.decode_image(&path, ImageOwner::Doll(id))?;
+
+            if let Some(doll) = factory.get_doll_mut(id) {
+                doll.image = image;
+            }
+        }
+
+        let mut fragment_paths = factory
+            .fragments()
+            .filter(|(_, fragment)| !fragment.path.is_empty())
+            .map(|(id, fragment)| (*id, fragment.path.clone()))
+            .collect::<Vec<(u32, String)>>();
+
+        fragment_paths.sort();
+
+        let mut skipped = vec![];
+        let mut skipped_ids = vec![];
+
+        for (id, path) in fragment_paths {
+            match self.decode_or_skip(&path, ImageOwner::Fragment(id), skip_corrupt)? {
+                Ok(image) => {
+                    if let Some(fragment) = factory.get_fragment_mut(id) {
+                        fragment.image = image;
+                    }
+                }
+                Err(lint) => {
+                    skipped.push(lint);
+                    skipped_ids.push(id);
+                }
+            }
+        }
+
+        if skipped_ids.is_empty() {
+            return Ok(skipped);
+        }
+
+        let slot_ids = factory.slots().map(|(id, _)| *id).collect::<Vec<u32>>();
+
+        for slot_id in slot_ids {
+            if let Some(slot) = factory.get_slot_mut(slot_id) {
+                slot.candidates.retain(|id| !skipped_ids.contains(id));
+            }
+        }
+
+        for id in skipped_ids {
+            factory.remove_fragment(id);
+        }
+
+        Ok(skipped)
+    }
+}
+
+/// Bevy asset loader for loading default fragments (.defaults.ron).
+#[derive(Default, TypePath)]
+pub struct PaperdollDefaultsLoader;
+
+impl AssetLoader for PaperdollDefaultsLoader {
+    type Asset = PaperdollDefaults;
+
+    type Settings = ();
+
+    type Error = PaperdollDefaultsLoaderError;
+
     async fn load(
         &self,
         reader: &mut dyn Reader,
@@ -35,14 +855,318 @@ impl AssetLoader for PaperdollLoader {
         let mut bytes = Vec::new();
         reader.read_to_end(&mut bytes).await?;
 
-        let factory = paperdoll_tar::read(bytes.as_slice())?;
+        let defaults = ron::de::from_bytes::<PaperdollDefaults>(&bytes)?;
+
+        Ok(defaults)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["defaults.ron"]
+    }
+}
+
+/// Bevy asset loader for loading palette swaps (.palette.ron).
+#[derive(Default, TypePath)]
+pub struct PaletteSwapLoader;
+
+impl AssetLoader for PaletteSwapLoader {
+    type Asset = PaletteSwap;
+
+    type Settings = ();
+
+    type Error = PaletteSwapLoaderError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &Self::Settings,
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
 
-        let paperdoll_asset = PaperdollAsset::new(factory);
+        let palette_swap = ron::de::from_bytes::<PaletteSwap>(&bytes)?;
 
-        Ok(paperdoll_asset)
+        Ok(palette_swap)
     }
 
     fn extensions(&self) -> &[&str] {
-        &[paperdoll_tar::EXTENSION_NAME]
+        &["palette.ron"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        pin::Pin,
+        task::{Context, Poll},
+    };
+
+    use bevy::asset::io::{ReaderNotSeekableError, SeekableReader};
+
+    use super::*;
+
+    /// A reader returning at most one byte per read.
+    struct ByteReader<'a>(&'a [u8]);
+
+    impl AsyncRead for ByteReader<'_> {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &mut [u8],
+        ) -> Poll<std::io::Result<usize>> {
+            let len = self.0.len().min(buf.len()).min(1);
+
+            buf[..len].copy_from_slice(&self.0[..len]);
+
+            self.0 = &self.0[len..];
+
+            Poll::Ready(Ok(len))
+        }
+    }
+
+    impl Reader for ByteReader<'_> {
+        fn seekable(&mut self) -> Result<&mut dyn SeekableReader, ReaderNotSeekableError> {
+            Err(ReaderNotSeekableError)
+        }
+    }
+
+    fn read_one_byte_at_a_time(bytes: &[u8]) -> Result<Vec<u8>, PaperdollLoaderError> {
+        let mut reader = ByteReader(bytes);
+
+        futures_lite::future::block_on(read_ppd_bytes(
+            &mut reader,
+            "basic.ppd",
+            &LoadLimits::default(),
+        ))
+    }
+
+    fn basic() -> PaperdollAsset {
+        PaperdollAsset::from_bytes(include_bytes!("../assets/basic.ppd")).unwrap()
+    }
+
+    /// Rewrites the entries of `basic.ppd`, dropping the ones mapped to [`None`].
+    fn repack(map: impl Fn(&str, Vec<u8>) -> Option<Vec<u8>>) -> Vec<u8> {
+        let limits = LoadLimits::default();
+
+        let source =
+            Source::read("basic.ppd", include_bytes!("../assets/basic.ppd"), &limits).unwrap();
+
+        let mut builder = tar::Builder::new(vec![]);
+
+        for (path, bytes) in source.entries {
+            let Some(bytes) = map(&path, bytes) else {
+                continue;
+            };
+
+            let mut header = tar::Header::new_gnu();
+
+            header.set_size(bytes.len() as u64);
+            header.set_mode(0o644);
+
+            builder
+                .append_data(&mut header, path, bytes.as_slice())
+                .unwrap();
+        }
+
+        builder.into_inner().unwrap()
+    }
+
+    /// Gets the id and path of the first fragment of `basic.ppd`.
+    fn first_fragment() -> (u32, String) {
+        let manifest = basic().to_manifest();
+
+        let fragment = &manifest.fragments[0];
+
+        (fragment.id(), fragment.path.clone())
+    }
+
+    fn parse(manifest: &str) -> (Manifest, Vec<u32>) {
+        ron::de::from_str::<DirManifest>(manifest)
+            .unwrap()
+            .into_parts()
+    }
+
+    #[test]
+    fn manifest_without_masks_reads_no_masks() {
+        let manifest = ron::ser::to_string(&basic().to_manifest()).unwrap();
+
+        let (manifest, masks) = parse(&manifest);
+
+        let factory = PaperdollFactory::from_manifest(manifest).unwrap();
+
+        assert!(masks.is_empty());
+        assert!(image_paths(&factory, &masks)
+            .iter()
+            .all(|path| !path.ends_with(".mask.png")));
+    }
+
+    #[test]
+    fn only_declared_masks_are_read() {
+        let manifest = basic().to_manifest();
+
+        let fragment = &manifest.fragments[0];
+
+        let (id, path) = (fragment.id(), mask_path(&fragment.path));
+
+        let manifest = ron::ser::to_string(&DirManifest::new(&manifest, vec![id])).unwrap();
+
+        let (manifest, masks) = parse(&manifest);
+
+        let factory = PaperdollFactory::from_manifest(manifest).unwrap();
+
+        let mask_paths = image_paths(&factory, &masks)
+            .into_iter()
+            .filter(|path| path.ends_with(".mask.png"))
+            .collect::<Vec<String>>();
+
+        assert_eq!(masks, vec![id]);
+        assert_eq!(mask_paths, vec![path]);
+    }
+
+    #[test]
+    fn errors_name_asset_and_entry() {
+        let settings = PaperdollLoaderSettings::default();
+
+        let err = read_ppd("pack.ppd", &[], &settings).err().unwrap();
+
+        assert!(matches!(err, PaperdollLoaderError::MissingManifest { .. }));
+        assert!(err.to_string().contains("'pack.ppd'"));
+
+        let (id, path) = first_fragment();
+
+        let bytes = repack(|entry, bytes| (entry != path).then_some(bytes));
+
+        match read_ppd("pack.ppd", &bytes, &settings) {
+            Err(PaperdollLoaderError::MissingImage { entry, owner, .. }) => {
+                assert_eq!(entry, path);
+                assert_eq!(owner, ImageOwner::Fragment(id));
+            }
+            result => panic!("Unexpected result: {:?}", result.err()),
+        }
+    }
+
+    #[test]
+    fn limits_are_enforced() {
+        let bytes = include_bytes!("../assets/basic.ppd");
+
+        let read = |limits: LoadLimits| {
+            let settings = PaperdollLoaderSettings {
+                limits,
+                ..default()
+            };
+
+            read_ppd("basic.ppd", bytes, &settings).err()
+        };
+
+        assert!(matches!(
+            read(LoadLimits {
+                max_file_size: Some(1024),
+                ..default()
+            }),
+            Some(PaperdollLoaderError::LimitExceeded {
+                limit: Limit::FileSize,
+                max: 1024,
+                ..
+            })
+        ));
+        assert!(matches!(
+            read(LoadLimits {
+                max_fragments: Some(1),
+                ..default()
+            }),
+            Some(PaperdollLoaderError::LimitExceeded {
+                limit: Limit::Fragments,
+                max: 1,
+                ..
+            })
+        ));
+        assert!(matches!(
+            read(LoadLimits {
+                max_total_pixels: Some(1),
+                ..default()
+            }),
+            Some(PaperdollLoaderError::DollLimitExceeded {
+                limit: Limit::TotalPixels,
+                ..
+            })
+        ));
+        assert!(read(LoadLimits {
+            max_file_size: Some(bytes.len() as u64),
+            ..default()
+        })
+        .is_none());
+    }
+
+    #[test]
+    fn corrupt_fragments_are_skipped() {
+        let (id, path) = first_fragment();
+
+        let bytes = repack(|entry, bytes| Some(if entry == path { vec![0; 16] } else { bytes }));
+
+        let strict = PaperdollLoaderSettings::default();
+
+        assert!(matches!(
+            read_ppd("basic.ppd", &bytes, &strict),
+            Err(PaperdollLoaderError::Image {
+                owner: ImageOwner::Fragment(fragment_id),
+                ..
+            }) if fragment_id == id
+        ));
+
+        let lenient = PaperdollLoaderSettings {
+            skip_corrupt_fragments: true,
+            ..default()
+        };
+
+        let asset = read_ppd("basic.ppd", &bytes, &lenient).unwrap();
+
+        assert!(asset.diagnostics().iter().any(|diagnostic| matches!(
+            &diagnostic.lint,
+            Lint::SkippedImage { entry, owner: ImageOwner::Fragment(fragment_id), .. }
+                if *entry == path && *fragment_id == id
+        )));
+
+        for slot in basic().to_manifest().slots {
+            assert!(asset
+                .get_fragments_by_slot(slot.id())
+                .iter()
+                .all(|fragment| fragment.id() != id));
+        }
+    }
+
+    #[test]
+    fn plain_archive_read_from_short_reads() {
+        let bytes = include_bytes!("../assets/basic.ppd");
+
+        assert_eq!(read_one_byte_at_a_time(bytes).unwrap(), bytes);
+    }
+
+    #[cfg(not(feature = "gzip"))]
+    #[test]
+    fn compression_detected_from_short_reads() {
+        assert!(matches!(
+            read_one_byte_at_a_time(&[0x1f, 0x8b, 0x08, 0x00]),
+            Err(PaperdollLoaderError::UnsupportedCompression {
+                compression: Compression::Gzip,
+                ..
+            })
+        ));
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn compression_detected_from_short_reads() {
+        let bytes = include_bytes!("../assets/basic.ppd");
+
+        let mut compressed = vec![];
+
+        futures_lite::future::block_on(futures_lite::AsyncReadExt::read_to_end(
+            &mut async_compression::futures::bufread::GzipEncoder::new(&bytes[..]),
+            &mut compressed,
+        ))
+        .unwrap();
+
+        assert_eq!(read_one_byte_at_a_time(&compressed).unwrap(), bytes);
     }
 }
@@ -0,0 +1,75 @@
+use bevy::prelude::*;
+
+/// Colors tinting the regions marked by the mask image of a fragment.
+///
+/// The red, green and blue channels of the mask mark the regions tinted by the primary,
+/// secondary and tertiary color respectively. Tinting multiplies the color of the fragment.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MaskColors {
+    pub primary: Color,
+    pub secondary: Color,
+    pub tertiary: Color,
+}
+
+impl Default for MaskColors {
+    fn default() -> Self {
+        Self {
+            primary: Color::WHITE,
+            secondary: Color::WHITE,
+            tertiary: Color::WHITE,
+        }
+    }
+}
+
+impl MaskColors {
+    pub(crate) fn apply(&self, pixel: [u8; 4], mask: [u8; 4]) -> [u8; 4] {
+        let mask_alpha = mask[3] as f32 / 255.0;
+
+        let weights = [
+            mask[0] as f32 / 255.0 * mask_alpha,
+            mask[1] as f32 / 255.0 * mask_alpha,
+            mask[2] as f32 / 255.0 * mask_alpha,
+        ];
+
+        let total = weights.iter().sum::<f32>();
+
+        if total <= 0.0 {
+            return pixel;
+        }
+
+        // Overlapping channels share the pixel instead of over-tinting it.
+        let scale = if total > 1.0 { 1.0 / total } else { 1.0 };
+
+        let tints = [
+            self.primary.to_srgba(),
+            self.secondary.to_srgba(),
+            self.tertiary.to_srgba(),
+        ];
+
+        let mut color = pixel;
+
+        for (channel, value) in color.iter_mut().take(3).enumerate() {
+            let source = pixel[channel] as f32;
+
+            let tinted = tints
+                .iter()
+                .zip(weights)
+                .map(|(tint, weight)| {
+                    let factor = match channel {
+                        0 => tint.red,
+                        1 => tint.green,
+                        _ => tint.blue,
+                    };
+
+                    source * factor * weight * scale
+                })
+                .sum::<f32>();
+
+            *value = (source * (1.0 - total * scale) + tinted)
+                .round()
+                .clamp(0.0, 255.0) as u8;
+        }
+
+        color
+    }
+}
@@ -0,0 +1,188 @@
+use anyhow::Result;
+use bevy::platform::collections::HashMap;
+use paperdoll_tar::paperdoll::{ImageData, PaperdollFactory, Point};
+
+/// A difference between a base asset and a pack merged into it, see
+/// [`crate::PaperdollAsset::merge`].
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum MergeConflict {
+    /// The fragment of the pack has the same name as a fragment of the base. Both are kept, the
+    /// fragment of the pack under a new id.
+    DuplicateFragment { fragment: u32, pack_fragment: u32 },
+    /// The slot of the pack has the same name as a slot of the base, but a different size,
+    /// anchor or positions. The slot of the base is kept.
+    SlotMismatch { slot: u32, pack_slot: u32 },
+}
+
+/// The result of merging a pack into a base asset.
+///
+/// Ids of the pack are mapped to the ids in the merged asset.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MergeReport {
+    pub dolls: HashMap<u32, u32>,
+    pub slots: HashMap<u32, u32>,
+    pub fragments: HashMap<u32, u32>,
+    pub conflicts: Vec<MergeConflict>,
+}
+
+/// Merges the dolls, slots and fragments of the pack into the factory.
+///
+/// Slots and dolls of the pack are matched by name (their descriptions). Fragments and unnamed
+/// slots and dolls are always added, without the image paths of the pack, which would clash with
+/// the paths of the factory.
+pub(crate) fn merge(
+    factory: &mut PaperdollFactory,
+    fragment_masks: &mut HashMap<u32, ImageData>,
+    pack: &PaperdollFactory,
+    pack_fragment_masks: &HashMap<u32, ImageData>,
+) -> Result<MergeReport> {
+    let mut report = MergeReport::default();
+
+    let mut pack_fragments = pack.fragments().collect::<Vec<_>>();
+
+    pack_fragments.sort_by_key(|(id, _)| **id);
+
+    for (pack_id, pack_fragment) in pack_fragments {
+        let clashing = factory
+            .fragments()
+            .find(|(_, fragment)| !fragment.desc.is_empty() && fragment.desc == pack_fragment.desc)
+            .map(|(id, _)| *id);
+
+        let id = factory.add_fragment()?;
+
+        if let Some(fragment) = factory.get_fragment_mut(id) {
+            fragment.desc = pack_fragment.desc.clone();
+            fragment.pivot = pack_fragment.pivot;
+            fragment.image = pack_fragment.image.clone();
+        }
+
+        if let Some(mask) = pack_fragment_masks.get(pack_id) {
+            fragment_masks.insert(id, mask.clone());
+        }
+
+        if let Some(clashing) = clashing {
+            report.conflicts.push(MergeConflict::DuplicateFragment {
+                fragment: clashing,
+                pack_fragment: *pack_id,
+            });
+        }
+
+        report.fragments.insert(*pack_id, id);
+    }
+
+    let mut pack_slots = pack.slots().collect::<Vec<_>>();
+
+    pack_slots.sort_by_key(|(id, _)| **id);
+
+    for (pack_id, pack_slot) in pack_slots {
+        let candidates = pack_slot
+            .candidates
+            .iter()
+            .filter_map(|fragment_id| report.fragments.get(fragment_id))
+            .copied()
+            .collect::<Vec<u32>>();
+
+        let existing = factory
+            .slots()
+            .find(|(_, slot)| !slot.desc.is_empty() && slot.desc == pack_slot.desc)
+            .map(|(id, _)| *id);
+
+        let id =
+            match existing.and_then(|id| factory.get_slot_mut(id)) {
+                Some(slot) => {
+                    if slot.width != pack_slot.width
+                        || slot.height != pack_slot.height
+                        || !is_same_point(&slot.anchor, &pack_slot.anchor)
+                        || slot.positions.len() != pack_slot.positions.len()
+                        || slot.positions.iter().zip(&pack_slot.positions).any(
+                            |(position, pack_position)| !is_same_point(position, pack_position),
+                        )
+                        || slot.constrainted != pack_slot.constrainted
+                    {
+                        report.conflicts.push(MergeConflict::SlotMismatch {
+                            slot: slot.id(),
+                            pack_slot: *pack_id,
+                        });
+                    }
+
+                    for fragment_id in candidates {
+                        if !slot.candidates.contains(&fragment_id) {
+                            slot.candidates.push(fragment_id);
+                        }
+                    }
+
+                    slot.id()
+                }
+                None => {
+                    let id = factory.add_slot()?;
+
+                    if let Some(slot) = factory.get_slot_mut(id) {
+                        slot.desc = pack_slot.desc.clone();
+                        slot.required = pack_slot.required;
+                        slot.constrainted = pack_slot.constrainted;
+                        slot.positions = pack_slot.positions.clone();
+                        slot.width = pack_slot.width;
+                        slot.height = pack_slot.height;
+                        slot.anchor = pack_slot.anchor;
+                        slot.candidates = candidates;
+                    }
+
+                    id
+                }
+            };
+
+        report.slots.insert(*pack_id, id);
+    }
+
+    let mut pack_dolls = pack.dolls().collect::<Vec<_>>();
+
+    pack_dolls.sort_by_key(|(id, _)| **id);
+
+    for (pack_id, pack_doll) in pack_dolls {
+        let slots = pack_doll
+            .slots
+            .iter()
+            .filter_map(|slot_id| report.slots.get(slot_id))
+            .copied()
+            .collect::<Vec<u32>>();
+
+        let existing = factory
+            .dolls()
+            .find(|(_, doll)| !doll.desc.is_empty() && doll.desc == pack_doll.desc)
+            .map(|(id, _)| *id);
+
+        let id = match existing.and_then(|id| factory.get_doll_mut(id).map(|doll| (id, doll))) {
+            Some((id, doll)) => {
+                for slot_id in slots {
+                    if !doll.slots.contains(&slot_id) {
+                        doll.slots.push(slot_id);
+                    }
+                }
+
+                id
+            }
+            None => {
+                let id = factory.add_doll()?;
+
+                if let Some(doll) = factory.get_doll_mut(id) {
+                    doll.desc = pack_doll.desc.clone();
+                    doll.width = pack_doll.width;
+                    doll.height = pack_doll.height;
+                    doll.offset = pack_doll.offset;
+                    doll.slots = slots;
+                    doll.image = pack_doll.image.clone();
+                }
+
+                id
+            }
+        };
+
+        report.dolls.insert(*pack_id, id);
+    }
+
+    Ok(report)
+}
+
+fn is_same_point(a: &Point, b: &Point) -> bool {
+    a.x == b.x && a.y == b.y
+}
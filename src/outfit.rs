@@ -0,0 +1,23 @@
+use crate::constraint::Constraint;
+
+/// A problem found in the selections of a paperdoll.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum OutfitProblem {
+    /// The doll does not exist.
+    UnknownDoll(u32),
+    /// The slot does not exist.
+    UnknownSlot(u32),
+    /// The slot exists but does not belong to the doll.
+    SlotNotInDoll(u32),
+    /// The fragment used in the slot does not exist.
+    UnknownFragment { slot: u32, fragment: u32 },
+    /// The fragment used in the slot is not one of its candidates.
+    NotCandidate { slot: u32, fragment: u32 },
+    /// The slot is required but empty.
+    MissingRequiredSlot(u32),
+    /// The fragments of the slots break the constraint.
+    ConstraintViolation {
+        constraint: Constraint,
+        slots: Vec<u32>,
+    },
+}
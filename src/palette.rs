@@ -0,0 +1,101 @@
+use anyhow::{bail, Result};
+use bevy::{color::ColorToPacked, prelude::*, reflect::TypePath};
+use serde::{Deserialize, Serialize};
+
+/// A single color replacement of a [`PaletteSwap`].
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ColorSwap {
+    /// The reference color the fragment is drawn with.
+    pub from: [u8; 3],
+    /// The color used instead.
+    pub to: [u8; 3],
+}
+
+/// A recoloring applied to fragments when compositing a paperdoll.
+///
+/// Palette swaps can be loaded from palette files (.palette.ron):
+///
+/// ```ron
+/// Colors([
+///     (from: (255, 0, 0), to: (120, 80, 40)),
+///     (from: (0, 255, 0), to: (200, 160, 120)),
+/// ])
+/// ```
+///
+/// or
+///
+/// ```ron
+/// GradientMap([(0, 0, 0, 255), (255, 200, 150, 255)])
+/// ```
+#[derive(Asset, Clone, Debug, Deserialize, PartialEq, Serialize, TypePath)]
+pub enum PaletteSwap {
+    /// Replaces each pixel matching a source color with the target color.
+    ///
+    /// Alpha of the pixel is kept.
+    Colors(Vec<ColorSwap>),
+    /// Maps the luminance of each pixel to a color of the gradient.
+    ///
+    /// Stops are evenly spaced from dark to bright.
+    GradientMap(Vec<[u8; 4]>),
+}
+
+impl PaletteSwap {
+    /// Creates a gradient map from the first row of the given image.
+    ///
+    /// # Errors
+    ///
+    /// - Will return an error if the image is empty or its pixels can not be read.
+    pub fn from_gradient_image(image: &Image) -> Result<Self> {
+        if image.width() == 0 || image.height() == 0 {
+            bail!("Gradient image is empty.")
+        }
+
+        let mut stops = Vec::with_capacity(image.width() as usize);
+
+        for x in 0..image.width() {
+            let color = image.get_color_at(x, 0)?;
+
+            stops.push(color.to_srgba().to_u8_array());
+        }
+
+        Ok(Self::GradientMap(stops))
+    }
+
+    pub(crate) fn apply(&self, pixel: [u8; 4]) -> [u8; 4] {
+        match self {
+            Self::Colors(swaps) => swaps
+                .iter()
+                .find(|swap| swap.from == [pixel[0], pixel[1], pixel[2]])
+                .map(|swap| [swap.to[0], swap.to[1], swap.to[2], pixel[3]])
+                .unwrap_or(pixel),
+            Self::GradientMap(stops) => {
+                if stops.is_empty() {
+                    return pixel;
+                }
+
+                let luminance =
+                    (pixel[0] as f32 * 0.299 + pixel[1] as f32 * 0.587 + pixel[2] as f32 * 0.114)
+                        / 255.0;
+
+                let position = luminance * (stops.len() - 1) as f32;
+
+                let index = (position.floor() as usize).min(stops.len() - 1);
+                let index_next = (index + 1).min(stops.len() - 1);
+
+                let t = position - index as f32;
+
+                let mut color = [0; 4];
+
+                for (channel, value) in color.iter_mut().enumerate() {
+                    *value = (stops[index][channel] as f32 * (1.0 - t)
+                        + stops[index_next][channel] as f32 * t)
+                        .round() as u8;
+                }
+
+                color[3] = (color[3] as u32 * pixel[3] as u32 / 255) as u8;
+
+                color
+            }
+        }
+    }
+}
@@ -1,6 +1,13 @@
 use bevy::prelude::*;
 
-use crate::{asset::PaperdollAsset, loader::PaperdollLoader};
+use crate::{
+    asset::PaperdollAsset,
+    defaults::PaperdollDefaults,
+    loader::{
+        PaletteSwapLoader, PaperdollDefaultsLoader, PaperdollLoader, PaperdollManifestLoader,
+    },
+    palette::PaletteSwap,
+};
 
 /// Bevy plugin for paperdoll.
 #[derive(Default)]
@@ -9,6 +16,11 @@ pub struct PaperdollPlugin;
 impl Plugin for PaperdollPlugin {
     fn build(&self, app: &mut App) {
         app.init_asset::<PaperdollAsset>()
-            .init_asset_loader::<PaperdollLoader>();
+            .init_asset::<PaletteSwap>()
+            .init_asset::<PaperdollDefaults>()
+            .init_asset_loader::<PaperdollLoader>()
+            .init_asset_loader::<PaperdollManifestLoader>()
+            .init_asset_loader::<PaletteSwapLoader>()
+            .init_asset_loader::<PaperdollDefaultsLoader>();
     }
 }
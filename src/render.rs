@@ -0,0 +1,524 @@
+use anyhow::{anyhow, bail, Result};
+use bevy::{
+    color::ColorToPacked,
+    platform::collections::{HashMap, HashSet},
+    prelude::*,
+};
+use paperdoll_tar::paperdoll::{ColorType, Fragment, ImageData, Paperdoll, PaperdollFactory, Slot};
+
+use crate::{
+    blend::SlotBlend,
+    clip::{ClipMask, ClipRegion, ClipTarget},
+    mask::MaskColors,
+    palette::PaletteSwap,
+    variant::{FragmentVariant, VariantCondition},
+};
+
+/// Per-paperdoll settings changing how a paperdoll is composited.
+#[derive(Clone, Default)]
+pub(crate) struct Modifiers {
+    pub(crate) palette_swap: Option<PaletteSwap>,
+    pub(crate) slot_blends: HashMap<u32, SlotBlend>,
+    pub(crate) slot_mask_colors: HashMap<u32, MaskColors>,
+    pub(crate) slot_palette_swaps: HashMap<u32, PaletteSwap>,
+}
+
+impl Modifiers {
+    fn is_empty(&self) -> bool {
+        self.palette_swap.is_none()
+            && self.slot_blends.is_empty()
+            && self.slot_mask_colors.is_empty()
+            && self.slot_palette_swaps.is_empty()
+    }
+}
+
+/// Composites paperdolls using the data of a paperdoll asset.
+pub(crate) struct Renderer<'a> {
+    pub(crate) factory: &'a PaperdollFactory,
+    pub(crate) fragment_clips: &'a HashMap<u32, Vec<ClipMask>>,
+    pub(crate) fragment_hidden_slots: &'a HashMap<u32, Vec<u32>>,
+    pub(crate) fragment_masks: &'a HashMap<u32, ImageData>,
+    pub(crate) fragment_variants: &'a HashMap<u32, Vec<FragmentVariant>>,
+    pub(crate) slot_blends: &'a HashMap<u32, SlotBlend>,
+}
+
+struct Canvas {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl Canvas {
+    fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![0; width as usize * height as usize * 4],
+        }
+    }
+
+    fn draw(&mut self, layer: &Layer) {
+        for (x, y) in layer.placement.pixels(layer.image, self.width, self.height) {
+            let index = y as usize * self.width as usize + x as usize;
+
+            if layer.clip.is_some_and(|clip| clip[index]) {
+                continue;
+            }
+
+            let Some(mut pixel) = layer.placement.sample(layer.image, x, y) else {
+                continue;
+            };
+
+            for palette_swap in &layer.palette_swaps {
+                pixel = palette_swap.apply(pixel);
+            }
+
+            if let Some((mask, mask_colors)) = &layer.mask {
+                if let Some(weights) = layer.placement.sample(mask, x, y) {
+                    pixel = mask_colors.apply(pixel, weights);
+                }
+            }
+
+            let index = index * 4;
+
+            let dst = [
+                self.pixels[index],
+                self.pixels[index + 1],
+                self.pixels[index + 2],
+                self.pixels[index + 3],
+            ];
+
+            self.pixels[index..index + 4].copy_from_slice(&layer.blend.composite(dst, pixel));
+        }
+    }
+
+    fn into_image_data(self) -> ImageData {
+        ImageData {
+            width: self.width,
+            height: self.height,
+            color_type: ColorType::Rgba,
+            pixels: self.pixels,
+        }
+    }
+}
+
+/// Where an image is placed on the canvas, with the rows of the given width.
+///
+/// Mirrors the placement of [`PaperdollFactory::analyse`] and the copying of
+/// [`PaperdollFactory::render`]: positions are truncated and fragments of constrained slots are
+/// not scaled, their pixels are read with the width of the slot.
+#[derive(Clone, Copy)]
+struct Placement {
+    left: i64,
+    top: i64,
+    width: u32,
+    height: u32,
+}
+
+impl Placement {
+    fn new(slot: &Slot, fragment: &Fragment, position_index: usize) -> Option<Self> {
+        let position = slot.positions.get(position_index)?;
+
+        if slot.constrainted {
+            Some(Self {
+                left: position.x as i64,
+                top: position.y as i64,
+                width: slot.width,
+                height: slot.height,
+            })
+        } else {
+            Some(Self {
+                left: (position.x + slot.anchor.x - fragment.pivot.x) as i64,
+                top: (position.y + slot.anchor.y - fragment.pivot.y) as i64,
+                width: fragment.image.width,
+                height: fragment.image.height,
+            })
+        }
+    }
+
+    /// Iterates the canvas coordinates covered by this placement.
+    ///
+    /// Rows are copied as long as the image has pixels for them, as upstream does.
+    fn pixels(
+        &self,
+        image: &ImageData,
+        canvas_width: u32,
+        canvas_height: u32,
+    ) -> impl Iterator<Item = (i64, i64)> {
+        let (canvas_width, canvas_height) = (canvas_width as i64, canvas_height as i64);
+        let (width, height) = (self.width as i64, self.height as i64);
+
+        let is_outside = width == 0
+            || self.left >= canvas_width
+            || self.left + width < 0
+            || self.top >= canvas_height
+            || self.top + height < 0;
+
+        let (x_range, y_range) = if is_outside {
+            (0..0, 0..0)
+        } else {
+            let source_x = (-self.left).max(0);
+            let source_y = (-self.top).max(0);
+
+            let x_start = self.left.max(0);
+            let y_start = self.top.max(0);
+
+            let copy_width = (width - source_x).min(canvas_width - x_start);
+
+            let remaining = image.pixels.len() as i64 - (source_y * width + source_x) * 4;
+            let source_rows = (remaining.max(0) + width * 4 - 1) / (width * 4);
+
+            let rows = source_rows.min(canvas_height - y_start);
+
+            (x_start..x_start + copy_width, y_start..y_start + rows)
+        };
+
+        y_range.flat_map(move |y| x_range.clone().map(move |x| (x, y)))
+    }
+
+    /// Maps canvas coordinates to the coordinates of the placed image.
+    fn image_position(&self, x: i64, y: i64) -> (u32, u32) {
+        ((x - self.left) as u32, (y - self.top) as u32)
+    }
+
+    /// Samples the image at the given canvas coordinates.
+    fn sample(&self, image: &ImageData, x: i64, y: i64) -> Option<[u8; 4]> {
+        let (x, y) = self.image_position(x, y);
+
+        let index = (y as usize * self.width as usize + x as usize) * 4;
+
+        image
+            .pixels
+            .get(index..index + 4)
+            .map(|pixel| [pixel[0], pixel[1], pixel[2], pixel[3]])
+    }
+}
+
+struct Layer<'a> {
+    image: &'a ImageData,
+    placement: Placement,
+    palette_swaps: Vec<&'a PaletteSwap>,
+    mask: Option<(&'a ImageData, &'a MaskColors)>,
+    blend: SlotBlend,
+    clip: Option<&'a [bool]>,
+}
+
+impl Renderer<'_> {
+    /// Renders the paperdoll, applying the modifiers and slot settings if it uses any.
+    ///
+    /// Without them the output is the same as [`PaperdollFactory::render_paperdoll`].
+    pub(crate) fn render(
+        &self,
+        paperdoll: &Paperdoll,
+        modifiers: Option<&Modifiers>,
+    ) -> Result<ImageData> {
+        let empty_modifiers = Modifiers::default();
+
+        let modifiers = modifiers.unwrap_or(&empty_modifiers);
+
+        if modifiers.is_empty() && !self.uses_slot_settings(paperdoll) {
+            return self.factory.render_paperdoll(paperdoll);
+        }
+
+        self.composite(paperdoll, modifiers)
+    }
+
+    /// Renders the paperdoll with the own compositor, even without modifiers and slot settings.
+    ///
+    /// Slots are drawn as [`PaperdollFactory::analyse`] lays them out: empty required slots use
+    /// their first candidate, and unknown slots or fragments fail.
+    pub(crate) fn composite(
+        &self,
+        paperdoll: &Paperdoll,
+        modifiers: &Modifiers,
+    ) -> Result<ImageData> {
+        let doll = self
+            .factory
+            .get_doll(paperdoll.doll)
+            .ok_or(anyhow!("Doll with id '{}' not found.", paperdoll.doll))?;
+
+        let mut canvas = Canvas::new(doll.width, doll.height);
+
+        if !doll.image.is_empty() {
+            canvas.draw(&Layer {
+                image: &doll.image,
+                placement: Placement {
+                    left: doll.offset.x as i64,
+                    top: doll.offset.y as i64,
+                    width: doll.image.width,
+                    height: doll.image.height,
+                },
+                palette_swaps: modifiers.palette_swap.iter().collect(),
+                mask: None,
+                blend: SlotBlend::default(),
+                clip: None,
+            });
+        }
+
+        let hidden_slots = self.hidden_slots(paperdoll);
+
+        let mut selections = vec![];
+
+        for slot_id in &doll.slots {
+            let slot = self
+                .factory
+                .get_slot(*slot_id)
+                .ok_or(anyhow!("Slot with id '{}' not found.", slot_id))?;
+
+            if hidden_slots.contains(slot_id) {
+                continue;
+            }
+
+            let Some(fragment_id) = self.drawn_fragment(paperdoll, *slot_id) else {
+                continue;
+            };
+
+            let fragment = self
+                .factory
+                .get_fragment(fragment_id)
+                .ok_or(anyhow!("Fragment with id '{}' not found.", fragment_id))?;
+
+            if fragment.image.is_empty() {
+                bail!(
+                    "Fragment with id '{}' is used but has no image.",
+                    fragment_id
+                )
+            }
+
+            selections.push((slot, fragment));
+        }
+
+        let clips = self.clips(&selections, doll.width, doll.height);
+
+        for (slot, fragment) in &selections {
+            let slot_id = slot.id();
+            let fragment_id = fragment.id();
+
+            let palette_swaps = modifiers
+                .slot_palette_swaps
+                .get(&slot_id)
+                .into_iter()
+                .chain(modifiers.palette_swap.iter())
+                .collect::<Vec<&PaletteSwap>>();
+
+            let mask = self
+                .fragment_masks
+                .get(&fragment_id)
+                .zip(modifiers.slot_mask_colors.get(&slot_id));
+
+            let blend = modifiers
+                .slot_blends
+                .get(&slot_id)
+                .or(self.slot_blends.get(&slot_id))
+                .copied()
+                .unwrap_or_default();
+
+            let clip = clips.get(&slot_id).map(|clip| clip.as_slice());
+
+            for position_index in 0..slot.positions.len() {
+                let Some(placement) = Placement::new(slot, fragment, position_index) else {
+                    continue;
+                };
+
+                canvas.draw(&Layer {
+                    image: &fragment.image,
+                    placement,
+                    palette_swaps: palette_swaps.clone(),
+                    mask,
+                    blend,
+                    clip,
+                });
+            }
+        }
+
+        Ok(canvas.into_image_data())
+    }
+
+    /// Gets the fragment drawn for the given slot, which is the first variant whose condition is
+    /// met, or the used fragment.
+    pub(crate) fn drawn_fragment(&self, paperdoll: &Paperdoll, slot_id: u32) -> Option<u32> {
+        let fragment_id = self.used_fragment(paperdoll, slot_id)?;
+
+        let variant = self
+            .fragment_variants
+            .get(&fragment_id)
+            .and_then(|variants| {
+                variants.iter().find(|variant| match &variant.condition {
+                    VariantCondition::SlotUsed(other_slot_id) => {
+                        *other_slot_id != slot_id && paperdoll.slot_map.contains_key(other_slot_id)
+                    }
+                    VariantCondition::FragmentUsed(other_fragment_id) => {
+                        paperdoll.slot_map.iter().any(|(id, fragment_id)| {
+                            *id != slot_id && fragment_id == other_fragment_id
+                        })
+                    }
+                    VariantCondition::FragmentInSlot { slot, fragment } => {
+                        *slot != slot_id && paperdoll.slot_map.get(slot) == Some(fragment)
+                    }
+                })
+            });
+
+        Some(variant.map_or(fragment_id, |variant| variant.fragment))
+    }
+
+    /// Gets the slots hidden by the fragments used in the paperdoll.
+    ///
+    /// Fragments in hidden slots do not hide other slots. Slots are checked from the top of the
+    /// draw order, so of two slots hiding each other, the one drawn above is kept.
+    pub(crate) fn hidden_slots(&self, paperdoll: &Paperdoll) -> HashSet<u32> {
+        let mut hidden_slots = HashSet::new();
+
+        let Some(doll) = self.factory.get_doll(paperdoll.doll) else {
+            return hidden_slots;
+        };
+
+        for slot_id in doll.slots.iter().rev() {
+            if hidden_slots.contains(slot_id) {
+                continue;
+            }
+
+            let Some(slot_ids) = self
+                .used_fragment(paperdoll, *slot_id)
+                .and_then(|fragment_id| self.fragment_hidden_slots.get(&fragment_id))
+            else {
+                continue;
+            };
+
+            hidden_slots.extend(slot_ids.iter().filter(|id| *id != slot_id));
+        }
+
+        hidden_slots
+    }
+
+    /// Gets the fragment used in the given slot, falling back to the first candidate of empty
+    /// required slots as [`PaperdollFactory::analyse`] does.
+    fn used_fragment(&self, paperdoll: &Paperdoll, slot_id: u32) -> Option<u32> {
+        paperdoll.slot_map.get(&slot_id).copied().or_else(|| {
+            self.factory
+                .get_slot(slot_id)
+                .filter(|slot| slot.required)
+                .and_then(|slot| slot.candidates.first().copied())
+        })
+    }
+
+    /// Checks whether the slot settings of the asset change how the paperdoll is drawn, so
+    /// paperdolls not using them are drawn by [`PaperdollFactory::render_paperdoll`].
+    fn uses_slot_settings(&self, paperdoll: &Paperdoll) -> bool {
+        let Some(doll) = self.factory.get_doll(paperdoll.doll) else {
+            return false;
+        };
+
+        if !self.hidden_slots(paperdoll).is_empty() {
+            return true;
+        }
+
+        doll.slots.iter().any(|slot_id| {
+            let Some(fragment_id) = self.used_fragment(paperdoll, *slot_id) else {
+                return false;
+            };
+
+            self.slot_blends
+                .get(slot_id)
+                .is_some_and(|blend| !blend.is_default())
+                || self
+                    .fragment_clips
+                    .get(&fragment_id)
+                    .is_some_and(|clip_masks| !clip_masks.is_empty())
+                || self.drawn_fragment(paperdoll, *slot_id) != Some(fragment_id)
+        })
+    }
+
+    /// Computes for each slot the canvas pixels hidden by the clip masks of other fragments.
+    fn clips(
+        &self,
+        selections: &[(&Slot, &Fragment)],
+        width: u32,
+        height: u32,
+    ) -> HashMap<u32, Vec<bool>> {
+        let mut clips: HashMap<u32, Vec<bool>> = HashMap::new();
+
+        for (z, (slot, fragment)) in selections.iter().enumerate() {
+            let Some(clip_masks) = self.fragment_clips.get(&fragment.id()) else {
+                continue;
+            };
+
+            for clip_mask in clip_masks {
+                let targets = selections
+                    .iter()
+                    .enumerate()
+                    .filter(|(target_z, (target_slot, _))| {
+                        *target_z != z
+                            && match &clip_mask.target {
+                                ClipTarget::Below => *target_z < z,
+                                ClipTarget::Slots(slot_ids) => slot_ids.contains(&target_slot.id()),
+                            }
+                    })
+                    .map(|(_, (target_slot, _))| target_slot.id())
+                    .collect::<Vec<u32>>();
+
+                if targets.is_empty() {
+                    continue;
+                }
+
+                for position_index in 0..slot.positions.len() {
+                    let Some(placement) = Placement::new(slot, fragment, position_index) else {
+                        continue;
+                    };
+
+                    for (x, y) in placement.pixels(&fragment.image, width, height) {
+                        let (image_x, image_y) = placement.image_position(x, y);
+
+                        let hidden = match &clip_mask.region {
+                            ClipRegion::Fragment => placement
+                                .sample(&fragment.image, x, y)
+                                .is_some_and(|pixel| pixel[3] > 0),
+                            ClipRegion::Rect(rect) => rect.contains(UVec2::new(image_x, image_y)),
+                        };
+
+                        if !hidden {
+                            continue;
+                        }
+
+                        let index = y as usize * width as usize + x as usize;
+
+                        for target in &targets {
+                            clips
+                                .entry(*target)
+                                .or_insert_with(|| vec![false; width as usize * height as usize])
+                                [index] = true;
+                        }
+                    }
+                }
+            }
+        }
+
+        clips
+    }
+}
+
+/// Converts a bevy image to RGBA pixels.
+pub(crate) fn image_data_from_image(image: &Image) -> Result<ImageData> {
+    let width = image.width();
+    let height = image.height();
+
+    if width == 0 || height == 0 {
+        bail!("Image is empty.")
+    }
+
+    let mut pixels = Vec::with_capacity(width as usize * height as usize * 4);
+
+    for y in 0..height {
+        for x in 0..width {
+            let color = image.get_color_at(x, y)?;
+
+            pixels.extend_from_slice(&color.to_srgba().to_u8_array());
+        }
+    }
+
+    Ok(ImageData {
+        width,
+        height,
+        color_type: ColorType::Rgba,
+        pixels,
+    })
+}
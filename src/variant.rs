@@ -0,0 +1,21 @@
+/// The condition under which a [`FragmentVariant`] is drawn.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum VariantCondition {
+    /// The slot is not empty.
+    SlotUsed(u32),
+    /// The fragment is used in any slot.
+    FragmentUsed(u32),
+    /// The fragment is used in the slot.
+    FragmentInSlot { slot: u32, fragment: u32 },
+}
+
+/// A fragment drawn instead of another one while the condition is met, eg. flattened hair under
+/// a hat.
+///
+/// The selection of the slot is not changed, only the drawn image.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct FragmentVariant {
+    pub condition: VariantCondition,
+    /// The fragment drawn instead.
+    pub fragment: u32,
+}